@@ -1,19 +1,21 @@
-use blake2b_simd::Params;
 use futures::{AsyncRead, AsyncReadExt};
+use std::sync::Arc;
+
 use ipld_core::{cid, cid::multihash::Multihash};
-use sha2::{Digest, Sha256};
 
 use crate::{
-    error::{CarDecodeError, HashCode},
-    varint::read_varint_u64,
+    error::{CarDecodeError, DigestMismatch, MultihashError},
+    multihash_verifier::{MultihashHasher, MultihashVerifier, MultihashVerifierRegistry},
+    varint::{read_varint_u64, U64_LEN},
     Cid,
 };
 
-const CODE_IDENTITY: u64 = 0x00;
-const CODE_SHA2_256: u64 = 0x12;
-const CODE_BLAKE2B_256: u64 = 0xb220;
+pub(crate) const CODE_IDENTITY: u64 = 0x00;
+pub(crate) const CODE_SHA2_256: u64 = 0x12;
 const DIGEST_SIZE: usize = 64;
 const CID_V0_MH_SIZE: usize = 32;
+/// Max byte length of a CID: version, codec, multihash code and size varints, and digest
+pub(crate) const MAX_CID_LEN: usize = 4 * U64_LEN + DIGEST_SIZE;
 
 pub(crate) async fn read_block_cid<R: AsyncRead + Unpin>(
     src: &mut R,
@@ -37,7 +39,7 @@ pub(crate) async fn read_block_cid<R: AsyncRead + Unpin>(
     // 1. Version as an unsigned varint (should be 1)
     // 2. Codec as an unsigned varint (valid according to the multicodec table)
     // 3. The raw bytes of a multihash
-    let version = cid::Version::try_from(version).unwrap();
+    let version = cid::Version::try_from(version)?;
     match version {
         cid::Version::V0 => Err(cid::Error::InvalidExplicitCidV0)?,
         cid::Version::V1 => {
@@ -55,17 +57,17 @@ async fn read_multihash<R: AsyncRead + Unpin>(
 ) -> Result<(Multihash<DIGEST_SIZE>, usize), CarDecodeError> {
     let (code, code_len) = read_varint_u64(r)
         .await?
-        .ok_or(CarDecodeError::InvalidMultihash(
-            "invalid code varint".to_string(),
-        ))?;
+        .ok_or(MultihashError::CodeVarint)?;
     let (size, size_len) = read_varint_u64(r)
         .await?
-        .ok_or(CarDecodeError::InvalidMultihash(
-            "invalid size varint".to_string(),
-        ))?;
+        .ok_or(MultihashError::SizeVarint)?;
 
-    if size > u8::MAX as u64 {
-        panic!("digest size {} > max {}", size, DIGEST_SIZE)
+    if size > DIGEST_SIZE as u64 {
+        return Err(MultihashError::DigestSize {
+            size,
+            max: DIGEST_SIZE as u64,
+        }
+        .into());
     }
 
     let mut digest = [0; DIGEST_SIZE];
@@ -73,65 +75,76 @@ async fn read_multihash<R: AsyncRead + Unpin>(
 
     // TODO: Sad, copies the digest (again)..
     // Multihash does not expose a way to construct Self without some decoding or copying
-    // unwrap: multihash must be valid since it's constructed manually
-    let mh = Multihash::wrap(code, &digest[..size as usize]).unwrap();
+    let mh = Multihash::wrap(code, &digest[..size as usize])?;
 
     Ok((mh, code_len + size_len + size as usize))
 }
 
-pub(crate) fn assert_block_cid(cid: &Cid, block: &[u8]) -> Result<(), CarDecodeError> {
-    let (hash_fn_name, block_digest) = match cid.hash().code() {
-        // TODO: Remove need to copy on .to_vec()
-        CODE_IDENTITY => ("identity", block.to_vec()),
-        CODE_SHA2_256 => ("sha2-256", hash_sha2_256(block).to_vec()),
-        CODE_BLAKE2B_256 => ("blake2b-256", hash_blake2b_256(block).to_vec()),
-        code => {
-            return Err(CarDecodeError::UnsupportedHashCode((
-                HashCode::Code(code),
-                *cid,
-            )));
-        }
-    };
+pub(crate) fn assert_block_cid(
+    cid: &Cid,
+    block: &[u8],
+    verifiers: &MultihashVerifierRegistry,
+) -> Result<(), CarDecodeError> {
+    let mut hasher = BlockHasher::new(cid, verifiers)?;
+    hasher.update(block);
+    hasher.verify()
+}
 
-    let cid_digest = cid.hash().digest();
+/// Incremental digest of a block checked against its CID, to verify a block read in chunks.
+/// Same checks as [`assert_block_cid`] over the concatenated chunks.
+pub(crate) struct BlockHasher {
+    cid: Cid,
+    verifier: Arc<dyn MultihashVerifier>,
+    hasher: Box<dyn MultihashHasher>,
+}
 
-    fn to_hex_lower(s: impl AsRef<[u8]>) -> String {
-        s.as_ref()
-            .iter()
-            .map(|i| format!("{i:02x}"))
-            .collect::<Vec<_>>()
-            .as_slice()
-            .join("")
+impl BlockHasher {
+    pub(crate) fn new(
+        cid: &Cid,
+        verifiers: &MultihashVerifierRegistry,
+    ) -> Result<Self, CarDecodeError> {
+        let code = cid.hash().code();
+        let verifier =
+            verifiers
+                .get_shared(code)
+                .ok_or_else(|| CarDecodeError::UnsupportedHashCode {
+                    code,
+                    cid: Box::new(*cid),
+                    location: None,
+                })?;
+
+        Ok(BlockHasher {
+            cid: *cid,
+            hasher: verifier.hasher_with_len(cid.hash().size() as usize),
+            verifier,
+        })
     }
 
-    if cid_digest != block_digest {
-        return Err(CarDecodeError::BlockDigestMismatch(format!(
-            "{} digest mismatch cid {:?} cid digest {} block digest {}",
-            hash_fn_name,
-            cid,
-            to_hex_lower(cid_digest),
-            to_hex_lower(block_digest),
-        )));
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
     }
 
-    Ok(())
-}
-
-fn hash_sha2_256(data: &[u8]) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    hasher.finalize().into()
-}
+    /// Compares the digest of all data passed to [`BlockHasher::update`] with the CID digest
+    pub(crate) fn verify(self) -> Result<(), CarDecodeError> {
+        let block_digest = self.hasher.finalize();
+
+        let cid_digest = self.cid.hash().digest();
+
+        if cid_digest != block_digest {
+            return Err(CarDecodeError::BlockDigestMismatch {
+                mismatch: Box::new(DigestMismatch {
+                    cid: self.cid,
+                    code: self.cid.hash().code(),
+                    hash: self.verifier.name().to_string(),
+                    expected: cid_digest.to_vec(),
+                    actual: block_digest,
+                }),
+                location: None,
+            });
+        }
 
-fn hash_blake2b_256(data: &[u8]) -> [u8; 32] {
-    Params::new()
-        .hash_length(32)
-        .to_state()
-        .update(data)
-        .finalize()
-        .as_bytes()
-        .try_into()
-        .unwrap()
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -141,8 +154,11 @@ mod tests {
     use futures::{executor, io::Cursor};
     use ipld_core::cid::{multihash::Multihash, Cid};
 
-    use super::{assert_block_cid, read_block_cid, read_multihash};
-    use crate::{block_cid::CODE_SHA2_256, error::CarDecodeError};
+    use super::{assert_block_cid, read_block_cid, read_multihash, BlockHasher};
+    use crate::{
+        block_cid::CODE_SHA2_256, error::CarDecodeError,
+        multihash_verifier::MultihashVerifierRegistry,
+    };
 
     const CID_V0_STR: &str = "QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf";
     const CID_V0_HEX: &str = "12205b0995ced69229d26009c53c185a62ea805a339383521edbed1028c496615448";
@@ -203,7 +219,7 @@ mod tests {
         let mut input_stream = from_hex("ffff");
 
         match executor::block_on(read_multihash(&mut input_stream)) {
-            Err(CarDecodeError::IoError(err)) => {
+            Err(CarDecodeError::IoError { source: err, .. }) => {
                 assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof)
             }
             x => panic!("other result {:?}", x),
@@ -215,6 +231,28 @@ mod tests {
         // simple dag-pb of string "helloworld"
         let cid = Cid::try_from("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf").unwrap();
         let block = hex::decode("0a110802120b68656c6c6f776f726c640a180b").unwrap();
-        assert_block_cid(&cid, &block).unwrap();
+        assert_block_cid(&cid, &block, &MultihashVerifierRegistry::default()).unwrap();
+    }
+
+    #[test]
+    fn block_hasher_chunks_same_as_whole_block() {
+        let cid = Cid::try_from("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf").unwrap();
+        let block = hex::decode("0a110802120b68656c6c6f776f726c640a180b").unwrap();
+
+        for chunk_size in [1, 3, block.len()] {
+            let mut hasher = BlockHasher::new(&cid, &MultihashVerifierRegistry::default()).unwrap();
+            for chunk in block.chunks(chunk_size) {
+                hasher.update(chunk);
+            }
+            hasher.verify().unwrap();
+        }
+
+        // Missing last byte
+        let mut hasher = BlockHasher::new(&cid, &MultihashVerifierRegistry::default()).unwrap();
+        hasher.update(&block[..block.len() - 1]);
+        assert!(matches!(
+            hasher.verify(),
+            Err(CarDecodeError::BlockDigestMismatch { .. })
+        ));
     }
 }
@@ -1,19 +1,33 @@
-use std::io;
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
 
-use futures::{AsyncRead, AsyncReadExt};
+use futures::{ready, AsyncRead, AsyncReadExt, FutureExt};
 
-use crate::{block_cid::read_block_cid, error::CarDecodeError, varint::read_varint_u64, Cid};
+use crate::{
+    block_cid::{read_block_cid, MAX_CID_LEN},
+    car_reader_options::{CarReaderOptions, Strictness},
+    error::{BlockHeaderError, CarDecodeError, Limit, SpecViolation},
+    varint::{
+        check_minimal_encoding, decode_varint_u64, encode_varint_u64, read_varint_u64,
+        varint_len_u64, U64_LEN,
+    },
+    Cid,
+};
 
-/// Arbitrary high value to prevent big allocations
-const MAX_BLOCK_LEN: u64 = 1073741824;
+/// (cid, block buffer, total block byte length including varint, cid len)
+pub(crate) type DecodedBlock = (Cid, Vec<u8>, usize, usize);
 
 /// # Returns
 ///
-/// (cid, block buffer, total block byte length including varint)
+/// (cid, block buffer, total block byte length including varint, cid len)
 pub(crate) async fn decode_block<R: AsyncRead + Unpin>(
     r: &mut R,
-) -> Result<(&mut R, Cid, Vec<u8>, usize), CarDecodeError> {
-    let (len, cid, varint_len, cid_len) = decode_block_header(r).await?;
+    options: &CarReaderOptions,
+) -> Result<DecodedBlock, CarDecodeError> {
+    let (len, cid, varint_len, cid_len) = decode_block_header(r, options).await?;
 
     // len from header = block_len - varint_len
     let block_len = len - cid_len;
@@ -21,39 +35,395 @@ pub(crate) async fn decode_block<R: AsyncRead + Unpin>(
     let mut block_buf = vec![0u8; block_len];
     r.read_exact(&mut block_buf).await?;
 
-    Ok((r, cid, block_buf, len + varint_len))
+    Ok((cid, block_buf, len + varint_len, cid_len))
 }
 
-async fn decode_block_header<R: AsyncRead + Unpin>(
+/// Resumable version of [`decode_block`] to poll from a `Stream`. A state machine that
+/// reads the section varint, then the CID, then the block payload, keeping the partially
+/// read section across `Poll::Pending`. No future is stored, so the reader does not
+/// need to be `Send` and nothing is boxed per block.
+#[derive(Debug, Default)]
+pub(crate) struct PollDecodeBlock {
+    state: PollDecodeBlockState,
+}
+
+#[derive(Debug)]
+enum PollDecodeBlockState {
+    /// Reading the section varint one byte at a time
+    Varint { buf: [u8; U64_LEN], read: usize },
+    /// Reading the CID. Up to [`MAX_CID_LEN`] bytes of the section are read at once,
+    /// bytes past the CID are the start of the payload.
+    Cid {
+        varint_len: usize,
+        section_len: usize,
+        buf: [u8; MAX_CID_LEN],
+        read: usize,
+    },
+    /// Reading the block payload after the CID
+    Payload {
+        cid: Cid,
+        cid_len: usize,
+        total_len: usize,
+        block: Vec<u8>,
+        read: usize,
+    },
+}
+
+impl Default for PollDecodeBlockState {
+    fn default() -> Self {
+        PollDecodeBlockState::Varint {
+            buf: [0; U64_LEN],
+            read: 0,
+        }
+    }
+}
+
+/// Section prefix `[varint|CID]` decoded by [`PollDecodeBlock::poll_decode_start()`]. The
+/// block payload is left in the reader, except for the bytes read along with the CID.
+pub(crate) struct BlockStart {
+    pub cid: Cid,
+    pub varint_len: usize,
+    pub cid_len: usize,
+    pub block_len: usize,
+    /// Start of the block payload in `prefix[..prefix_len]`
+    pub prefix: [u8; MAX_CID_LEN],
+    pub prefix_len: usize,
+}
+
+impl PollDecodeBlock {
+    /// Same result as [`decode_block`] for the section at absolute byte `offset` of the
+    /// CAR stream. After returning `Poll::Ready` it is ready to decode the next section.
+    pub(crate) fn poll_decode<R: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        r: &mut R,
+        offset: u64,
+        options: &CarReaderOptions,
+    ) -> Poll<Result<DecodedBlock, CarDecodeError>> {
+        let result = ready!(self.poll_decode_inner(cx, r, offset, options));
+        self.state = PollDecodeBlockState::default();
+        Poll::Ready(result)
+    }
+
+    /// Decodes the section varint and CID only, to read the block payload separately.
+    /// After returning `Poll::Ready` it is ready to decode the section after the payload.
+    pub(crate) fn poll_decode_start<R: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        r: &mut R,
+        offset: u64,
+        options: &CarReaderOptions,
+    ) -> Poll<Result<BlockStart, CarDecodeError>> {
+        let result = ready!(self.poll_decode_start_inner(cx, r, offset, options));
+        self.state = PollDecodeBlockState::default();
+        Poll::Ready(result)
+    }
+
+    fn poll_decode_inner<R: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        r: &mut R,
+        offset: u64,
+        options: &CarReaderOptions,
+    ) -> Poll<Result<DecodedBlock, CarDecodeError>> {
+        if !matches!(self.state, PollDecodeBlockState::Payload { .. }) {
+            let start = match ready!(self.poll_decode_start_inner(cx, r, offset, options)) {
+                Ok(start) => start,
+                Err(err) => return Poll::Ready(Err(err)),
+            };
+
+            let mut block = vec![0u8; start.block_len];
+            block[..start.prefix_len].copy_from_slice(&start.prefix[..start.prefix_len]);
+
+            self.state = PollDecodeBlockState::Payload {
+                cid: start.cid,
+                cid_len: start.cid_len,
+                total_len: start.varint_len + start.cid_len + start.block_len,
+                block,
+                read: start.prefix_len,
+            };
+        }
+
+        let PollDecodeBlockState::Payload {
+            cid,
+            cid_len,
+            total_len,
+            block,
+            read,
+        } = &mut self.state
+        else {
+            unreachable!("state is set to Payload above")
+        };
+
+        while *read < block.len() {
+            match ready!(poll_read_some(cx, r, &mut block[*read..])) {
+                Err(err) => return Poll::Ready(Err(err.into())),
+                Ok(n) => *read += n,
+            }
+        }
+
+        Poll::Ready(Ok((*cid, std::mem::take(block), *total_len, *cid_len)))
+    }
+
+    fn poll_decode_start_inner<R: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        r: &mut R,
+        offset: u64,
+        options: &CarReaderOptions,
+    ) -> Poll<Result<BlockStart, CarDecodeError>> {
+        loop {
+            match &mut self.state {
+                PollDecodeBlockState::Varint { buf, read } => {
+                    if *read == U64_LEN {
+                        return Poll::Ready(Err(BlockHeaderError::InvalidVarint.into()));
+                    }
+
+                    match ready!(poll_read_some(cx, r, &mut buf[*read..*read + 1])) {
+                        // Same as decode_block, an EOF within the varint is a clean end
+                        // unless strict
+                        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                            return Poll::Ready(Err(block_start_eof(*read, options)))
+                        }
+                        Err(err) => return Poll::Ready(Err(err.into())),
+                        Ok(n) => *read += n,
+                    }
+
+                    if let Some((len, varint_len)) = decode_varint_u64(&buf[..*read]) {
+                        if let Err(err) = check_section_len(len, varint_len, options)
+                            .and_then(|_| check_section_end(offset, varint_len, len, options))
+                        {
+                            return Poll::Ready(Err(err));
+                        }
+                        self.state = PollDecodeBlockState::Cid {
+                            varint_len,
+                            section_len: len as usize,
+                            buf: [0; MAX_CID_LEN],
+                            read: 0,
+                        };
+                    }
+                }
+
+                PollDecodeBlockState::Cid {
+                    varint_len,
+                    section_len,
+                    buf,
+                    read,
+                } => {
+                    let max_read = MAX_CID_LEN.min(*section_len);
+                    match ready!(poll_read_some(cx, r, &mut buf[*read..max_read])) {
+                        Err(err) => return Poll::Ready(Err(err.into())),
+                        Ok(n) => *read += n,
+                    }
+
+                    let (cid, cid_len) = match decode_block_cid(&buf[..*read]) {
+                        Ok(cid) => cid,
+                        // CID is incomplete, read more unless it overflows the section
+                        Err(err) if is_eof(&err) && *read < max_read => continue,
+                        Err(err) if is_eof(&err) && *read == *section_len => {
+                            return Poll::Ready(Err(cid_past_section(*section_len as u64)))
+                        }
+                        Err(err) => return Poll::Ready(Err(err)),
+                    };
+                    if let Err(err) =
+                        check_minimal_encoding(cid_len, cid.encoded_len(), options.strictness)
+                    {
+                        return Poll::Ready(Err(err));
+                    }
+
+                    // Bytes read past the CID are the start of the payload
+                    let prefix_len = *read - cid_len;
+                    let mut prefix = [0u8; MAX_CID_LEN];
+                    prefix[..prefix_len].copy_from_slice(&buf[cid_len..*read]);
+
+                    return Poll::Ready(Ok(BlockStart {
+                        cid,
+                        varint_len: *varint_len,
+                        cid_len,
+                        block_len: *section_len - cid_len,
+                        prefix,
+                        prefix_len,
+                    }));
+                }
+
+                PollDecodeBlockState::Payload { .. } => {
+                    unreachable!("payload state is only polled by poll_decode_inner")
+                }
+            }
+        }
+    }
+}
+
+/// Same as [`decode_block`] from the start of `buf`, for the section at absolute byte
+/// `offset` of the CAR stream. Returns `None` if `buf` ends before the section does.
+pub(crate) fn decode_block_prefix(
+    buf: &[u8],
+    offset: u64,
+    options: &CarReaderOptions,
+) -> Result<Option<DecodedBlock>, CarDecodeError> {
+    let (len, varint_len) = match decode_varint_u64(buf) {
+        Some(varint) => varint,
+        None if buf.len() < U64_LEN => return Ok(None),
+        None => return Err(BlockHeaderError::InvalidVarint.into()),
+    };
+
+    check_section_len(len, varint_len, options)?;
+    check_section_end(offset, varint_len, len, options)?;
+
+    let section_end = varint_len.saturating_add(len as usize);
+    let section = match buf.get(varint_len..section_end) {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let (cid, cid_len) = match decode_block_cid(section) {
+        Err(err) if is_eof(&err) => return Err(cid_past_section(len)),
+        result => result?,
+    };
+    check_minimal_encoding(cid_len, cid.encoded_len(), options.strictness)?;
+
+    Ok(Some((
+        cid,
+        section[cid_len..].to_vec(),
+        section_end,
+        cid_len,
+    )))
+}
+
+/// Decodes a CID from the start of `buf` with [`read_block_cid`]
+fn decode_block_cid(buf: &[u8]) -> Result<(Cid, usize), CarDecodeError> {
+    read_block_cid(&mut &buf[..])
+        .now_or_never()
+        .expect("reads from a slice are never pending")
+}
+
+/// Polls a single read into a non-empty `buf`. An EOF is returned as `UnexpectedEof`.
+pub(crate) fn poll_read_some<R: AsyncRead + Unpin>(
+    cx: &mut Context<'_>,
+    r: &mut R,
+    buf: &mut [u8],
+) -> Poll<io::Result<usize>> {
+    loop {
+        match Pin::new(&mut *r).poll_read(cx, buf) {
+            Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+            Poll::Ready(Err(err)) if err.kind() == io::ErrorKind::Interrupted => continue,
+            poll => return poll,
+        }
+    }
+}
+
+/// Rejects section lengths that can not be valid or would allocate too much
+fn check_section_len(
+    len: u64,
+    varint_len: usize,
+    options: &CarReaderOptions,
+) -> Result<(), CarDecodeError> {
+    check_minimal_encoding(varint_len, varint_len_u64(len), options.strictness)?;
+
+    if len == 0 {
+        return Err(BlockHeaderError::ZeroLength.into());
+    }
+
+    if len > options.max_block_len {
+        return Err(CarDecodeError::LimitExceeded {
+            limit: Limit::BlockLen,
+            value: len,
+            max: options.max_block_len,
+            location: None,
+        });
+    }
+
+    Ok(())
+}
+
+/// Rejects a section at absolute byte `offset` that ends past `options.max_total_bytes`
+fn check_section_end(
+    offset: u64,
+    varint_len: usize,
+    len: u64,
+    options: &CarReaderOptions,
+) -> Result<(), CarDecodeError> {
+    if let Some(max_total_bytes) = options.max_total_bytes {
+        let section_end = offset.saturating_add(varint_len as u64).saturating_add(len);
+        if section_end > max_total_bytes {
+            return Err(CarDecodeError::LimitExceeded {
+                limit: Limit::TotalBytes,
+                value: section_end,
+                max: max_total_bytes,
+                location: None,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// # Returns
+///
+/// (section len after varint, cid, varint len, cid len)
+pub(crate) async fn decode_block_header<R: AsyncRead + Unpin>(
     src: &mut R,
+    options: &CarReaderOptions,
 ) -> Result<(usize, Cid, usize, usize), CarDecodeError> {
-    let (len, varint_len) = match read_varint_u64(src).await {
-        Ok(Some(len)) => len,
-        Ok(None) => {
-            return Err(CarDecodeError::InvalidBlockHeader(
-                "invalid block header varint".to_string(),
-            ))
+    // First byte apart, to tell an EOF at the section start from one inside the varint
+    let mut first = [0u8; 1];
+    match src.read_exact(&mut first).await {
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+            return Err(block_start_eof(0, options))
         }
+        result => result?,
+    }
+    let (len, varint_len) = match read_varint_u64(&mut first.chain(&mut *src)).await {
+        Ok(Some(len)) => len,
+        Ok(None) => return Err(BlockHeaderError::InvalidVarint.into()),
         Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
-            return Err(CarDecodeError::BlockStartEOF)
+            return Err(block_start_eof(1, options))
         }
         Err(err) => Err(err)?,
     };
 
-    if len == 0 {
-        return Err(CarDecodeError::InvalidBlockHeader(
-            "zero length".to_string(),
-        ));
-    }
+    check_section_len(len, varint_len, options)?;
+
+    // Same as the streaming decoder, a CID longer than its section is a section error
+    let mut section = src.take(len);
+    let (cid, cid_len) = match read_block_cid(&mut section).await {
+        Err(err) if is_eof(&err) && section.limit() == 0 => return Err(cid_past_section(len)),
+        result => result?,
+    };
+    check_minimal_encoding(cid_len, cid.encoded_len(), options.strictness)?;
 
-    if len > MAX_BLOCK_LEN {
-        return Err(CarDecodeError::InvalidBlockHeader(format!(
-            "block len too big {}",
-            len
-        )));
+    Ok((len as usize, cid, varint_len, cid_len))
+}
+
+fn is_eof(err: &CarDecodeError) -> bool {
+    matches!(err, CarDecodeError::IoError { source, .. } if source.kind() == io::ErrorKind::UnexpectedEof)
+}
+
+fn cid_past_section(section_len: u64) -> CarDecodeError {
+    BlockHeaderError::CidPastSection { section_len }.into()
+}
+
+/// Error of a stream that ends after `read` bytes of a section varint. A clean end, except
+/// inside the varint in strict mode.
+fn block_start_eof(read: usize, options: &CarReaderOptions) -> CarDecodeError {
+    if read > 0 && options.strictness == Strictness::Strict {
+        return SpecViolation::TruncatedSectionVarint { len: read }.into();
     }
+    CarDecodeError::BlockStartEOF { location: None }
+}
 
-    let (cid, cid_len) = read_block_cid(src).await?;
+/// Encodes the section prefix of a block, everything before the block data
+///
+/// ```nn
+/// [varint|CID]
+/// ```
+pub(crate) fn encode_block_header(cid: &Cid, block_len: usize) -> Vec<u8> {
+    let cid_len = cid.encoded_len();
+    let mut varint_buf = [0u8; U64_LEN];
+    let varint = encode_varint_u64((cid_len + block_len) as u64, &mut varint_buf);
 
-    Ok((len as usize, cid, varint_len, cid_len))
+    let mut buf = Vec::with_capacity(varint.len() + cid_len);
+    buf.extend_from_slice(varint);
+    // unwrap: writing to a Vec never fails
+    cid.write_bytes(&mut buf).unwrap();
+    buf
 }
@@ -0,0 +1,449 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    car_header::{read_car_header, CarHeader, StreamEnd},
+    car_index::CarIndexBuilder,
+    car_reader_options::CarReaderOptions,
+    car_section::SectionStream,
+    car_writer::{
+        write_carv2_index_and_header, write_carv2_placeholder_header, CarV2WriterOptions,
+    },
+    error::{CarConvertError, CarDecodeError, CarEncodeError},
+};
+
+/// Size of the buffer used to copy the data payload in [`extract_v1`]
+const COPY_BUF_LEN: usize = 8 * 1024;
+
+/// Converts a CAR stream into an indexed CARv2 stream. The inner CARv1 header and all
+/// sections are copied as read, without re-encoding, while a fresh index is built.
+/// Equivalent to go-car's `WrapV1File`.
+///
+/// A CARv1 stream is wrapped as is. A CARv2 stream has its data payload re-wrapped,
+/// and any existing index is replaced.
+///
+/// # Examples
+///
+/// ```
+/// use rs_car::{wrap_v1, CarIndexedReader};
+/// use futures::io::Cursor;
+///
+/// #[async_std::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///   let mut r = async_std::fs::File::open("./tests/go_car_fixtures/sample-v1.car").await?;
+///   let mut w = Cursor::new(Vec::new());
+///
+///   wrap_v1(&mut r, &mut w, Default::default()).await?;
+///
+///   w.set_position(0);
+///   let car_reader = CarIndexedReader::new(w, true).await?;
+///   println!("{} blocks indexed", car_reader.index().len());
+///
+///   Ok(())
+/// }
+/// ```
+pub async fn wrap_v1<R, W>(
+    r: &mut R,
+    w: &mut W,
+    options: CarV2WriterOptions,
+) -> Result<(), CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + AsyncSeek + Unpin,
+{
+    let reader_options = CarReaderOptions::default().with_validate_block_hash(false);
+    wrap_v1_with_options(r, w, options, reader_options).await
+}
+
+/// Same as [`wrap_v1()`] with custom limits and checks to read `r`. Blocks are checked
+/// against their CID if [`CarReaderOptions::validate_block_hash`] is set.
+pub async fn wrap_v1_with_options<R, W>(
+    r: &mut R,
+    w: &mut W,
+    options: CarV2WriterOptions,
+    reader_options: CarReaderOptions,
+) -> Result<(), CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + AsyncSeek + Unpin,
+{
+    let mut r = RecordingReader::new(r);
+    let header = read_car_header(&mut r, &reader_options).await?;
+
+    let start = write_carv2_placeholder_header(w, &options)
+        .await
+        .map_err(CarEncodeError::from)?;
+    w.write_all(recorded_header_v1(&header, &r.take_recorded()))
+        .await
+        .map_err(CarEncodeError::from)?;
+
+    let mut sections = SectionStream::new(&header, reader_options);
+    let mut index = CarIndexBuilder::default();
+    let mut data_size = header.header_v1_len;
+    while let Some(section) = sections.next_section(&mut r).await {
+        let section = section?;
+        index.insert(&section.cid, data_size as u64);
+        w.write_all(&r.take_recorded())
+            .await
+            .map_err(CarEncodeError::from)?;
+        data_size += section.section_len();
+    }
+
+    write_carv2_index_and_header(w, start, &options, index, data_size)
+        .await
+        .map_err(CarEncodeError::from)?;
+
+    Ok(())
+}
+
+/// Extracts the inner CARv1 data payload of a CAR stream. The CARv1 header and all
+/// sections are copied as read, without re-encoding. Equivalent to go-car's `ExtractV1File`.
+///
+/// A CARv1 stream is copied as is. For a CARv2 stream the pragma, header, padding and
+/// index are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use rs_car::{car_read_all, extract_v1};
+///
+/// #[async_std::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///   let mut r = async_std::fs::File::open("./tests/go_car_fixtures/sample-wrapped-v2.car").await?;
+///   let mut car_v1 = vec![];
+///
+///   extract_v1(&mut r, &mut car_v1).await?;
+///
+///   let (blocks, header) = car_read_all(&mut car_v1.as_slice(), true).await?;
+///   println!("{:?} {} blocks", header, blocks.len());
+///
+///   Ok(())
+/// }
+/// ```
+pub async fn extract_v1<R, W>(r: &mut R, w: &mut W) -> Result<(), CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    extract_v1_with_options(r, w, CarReaderOptions::default()).await
+}
+
+/// Same as [`extract_v1()`] with custom limits and checks of the CAR header. Sections are
+/// copied without decoding them.
+pub async fn extract_v1_with_options<R, W>(
+    r: &mut R,
+    w: &mut W,
+    options: CarReaderOptions,
+) -> Result<(), CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut recording_r = RecordingReader::new(&mut *r);
+    let header = read_car_header(&mut recording_r, &options).await?;
+    w.write_all(recorded_header_v1(&header, &recording_r.take_recorded()))
+        .await
+        .map_err(CarEncodeError::from)?;
+
+    let offset = header.sections_offset();
+    match header.eof_stream {
+        StreamEnd::AfterNBytes { blocks_len, .. } => {
+            let copied = copy_data(&mut r.take(blocks_len as u64), w, offset).await?;
+            if copied < blocks_len as u64 {
+                return Err(
+                    CarDecodeError::from(io::Error::from(io::ErrorKind::UnexpectedEof))
+                        .located(offset + copied, None)
+                        .into(),
+                );
+            }
+        }
+        StreamEnd::OnBlockEOF => {
+            copy_data(r, w, offset).await?;
+        }
+    }
+
+    w.flush().await.map_err(CarEncodeError::from)?;
+
+    Ok(())
+}
+
+/// Copies `r` to `w` until the end of `r`, keeping read and write errors apart. Read
+/// errors are located from `offset`, the position of `r` in the CAR stream. Returns the
+/// byte length copied.
+async fn copy_data<R, W>(r: &mut R, w: &mut W, offset: u64) -> Result<u64, CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0; COPY_BUF_LEN];
+    let mut copied = 0;
+    loop {
+        let len = match r.read(&mut buf).await {
+            Ok(0) => return Ok(copied),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                return Err(CarDecodeError::from(err)
+                    .located(offset + copied, None)
+                    .into())
+            }
+        };
+        w.write_all(&buf[..len])
+            .await
+            .map_err(CarEncodeError::from)?;
+        copied += len as u64;
+    }
+}
+
+/// Returns the raw inner CARv1 header from all bytes read by [`read_car_header`]
+fn recorded_header_v1<'a>(header: &CarHeader, recorded: &'a [u8]) -> &'a [u8] {
+    &recorded[recorded.len() - header.header_v1_len..]
+}
+
+/// Forwards reads to the inner reader keeping a copy of all bytes read
+struct RecordingReader<R> {
+    r: R,
+    recorded: Vec<u8>,
+}
+
+impl<R> RecordingReader<R> {
+    fn new(r: R) -> Self {
+        RecordingReader {
+            r,
+            recorded: vec![],
+        }
+    }
+
+    /// Returns all bytes read since the last call
+    fn take_recorded(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.recorded)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for RecordingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let me = Pin::into_inner(self);
+        let poll = Pin::new(&mut me.r).poll_read(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            me.recorded.extend_from_slice(&buf[..n]);
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor, io::Cursor};
+
+    use super::*;
+    use crate::{car_index::CarIndexCodec, car_read_all, CarIndexedReader, Limit};
+
+    #[test]
+    fn wrap_v1_go_car_sample() {
+        executor::block_on(async {
+            let car_v1 = std::fs::read("./tests/go_car_fixtures/sample-v1.car").unwrap();
+            let mut w = Cursor::new(Vec::new());
+
+            let options = CarV2WriterOptions {
+                index_codec: CarIndexCodec::MultihashSorted,
+                ..Default::default()
+            };
+            wrap_v1(&mut car_v1.as_slice(), &mut w, options)
+                .await
+                .unwrap();
+
+            // Same bytes as go-car's WrapV1File
+            let car_v2 = std::fs::read("./tests/go_car_fixtures/sample-wrapped-v2.car").unwrap();
+            assert_eq!(w.get_ref().len(), car_v2.len());
+            assert!(w.get_ref() == &car_v2);
+        })
+    }
+
+    #[test]
+    fn wrap_v1_padding_then_extract() {
+        executor::block_on(async {
+            let car_v1 = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+            let mut w = Cursor::new(Vec::new());
+
+            let options = CarV2WriterOptions {
+                data_padding: 5,
+                index_padding: 9,
+                ..Default::default()
+            };
+            wrap_v1(&mut car_v1.as_slice(), &mut w, options)
+                .await
+                .unwrap();
+
+            // Indexed, with the same blocks
+            let car_v2 = w.into_inner();
+            let (blocks, _) = car_read_all(&mut car_v1.as_slice(), true).await.unwrap();
+            let mut car_reader = CarIndexedReader::new(Cursor::new(car_v2.clone()), true)
+                .await
+                .unwrap();
+            for (cid, block) in blocks {
+                assert_eq!(car_reader.get(&cid).await.unwrap(), Some(block));
+            }
+
+            let mut car_v1_out = vec![];
+            extract_v1(&mut car_v2.as_slice(), &mut car_v1_out)
+                .await
+                .unwrap();
+            assert_eq!(hex::encode(car_v1_out), hex::encode(car_v1));
+        })
+    }
+
+    #[test]
+    fn wrap_v1_of_carv2_indexless() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/go_car_fixtures/sample-v2-indexless.car").unwrap();
+            let mut w = Cursor::new(Vec::new());
+
+            let options = CarV2WriterOptions {
+                index_codec: CarIndexCodec::MultihashSorted,
+                ..Default::default()
+            };
+            wrap_v1(&mut car.as_slice(), &mut w, options).await.unwrap();
+
+            // Same data payload as sample-v1.car
+            let car_v2 = std::fs::read("./tests/go_car_fixtures/sample-wrapped-v2.car").unwrap();
+            assert!(w.get_ref() == &car_v2);
+        })
+    }
+
+    #[test]
+    fn extract_v1_go_car_sample() {
+        executor::block_on(async {
+            for car_filepath in [
+                "./tests/go_car_fixtures/sample-wrapped-v2.car",
+                "./tests/go_car_fixtures/sample-v2-indexless.car",
+                "./tests/go_car_fixtures/sample-v1.car",
+            ] {
+                let car = std::fs::read(car_filepath).unwrap();
+                let mut car_v1 = vec![];
+                extract_v1(&mut car.as_slice(), &mut car_v1).await.unwrap();
+
+                let expected = std::fs::read("./tests/go_car_fixtures/sample-v1.car").unwrap();
+                assert!(car_v1 == expected, "{}", car_filepath);
+            }
+        })
+    }
+
+    #[test]
+    fn extract_v1_truncated() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/go_car_fixtures/sample-v2-indexless.car").unwrap();
+            let mut car_v1 = vec![];
+            let err = extract_v1(&mut &car[..car.len() - 1], &mut car_v1)
+                .await
+                .unwrap_err();
+            // Stream cut 1 byte before the data payload end at byte 479958
+            assert_eq!(
+                err.to_string(),
+                "decoding the input: io error: unexpected end of file at byte 479957"
+            );
+        })
+    }
+
+    #[test]
+    fn convert_write_error() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/go_car_fixtures/sample-v2-indexless.car").unwrap();
+
+            // Output too short for the data payload
+            let mut out = [0u8; 1000];
+            let err = extract_v1(&mut car.as_slice(), &mut Cursor::new(&mut out[..]))
+                .await
+                .unwrap_err();
+            match err {
+                CarConvertError::Encode(CarEncodeError::IoError { source: err }) => {
+                    assert_eq!(err.kind(), io::ErrorKind::WriteZero)
+                }
+                x => panic!("other result {:?}", x),
+            }
+
+            let mut out = [0u8; 1000];
+            let err = wrap_v1(
+                &mut car.as_slice(),
+                &mut Cursor::new(&mut out[..]),
+                Default::default(),
+            )
+            .await
+            .unwrap_err();
+            assert!(matches!(err, CarConvertError::Encode(_)), "{:?}", err);
+        })
+    }
+
+    #[test]
+    fn convert_with_options() {
+        executor::block_on(async {
+            // Header of 100 bytes, then a first section at byte 100 of 91 bytes after its
+            // 1-byte varint
+            let mut car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+
+            let options = CarReaderOptions::default().with_max_header_len(98);
+            let err = extract_v1_with_options(&mut car.as_slice(), &mut vec![], options)
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    CarConvertError::Decode(CarDecodeError::LimitExceeded {
+                        limit: Limit::HeaderLen,
+                        ..
+                    })
+                ),
+                "{:?}",
+                err
+            );
+
+            let options = CarReaderOptions::default().with_max_block_len(90);
+            let err = wrap_v1_with_options(
+                &mut car.as_slice(),
+                &mut Cursor::new(Vec::new()),
+                Default::default(),
+                options,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "decoding the input: section length 91 exceeds max 90 in section 0 at byte 100"
+            );
+
+            // Last byte of the first block, only checked if enabled
+            car[100 + 92 - 1] ^= 0xff;
+            wrap_v1(
+                &mut car.as_slice(),
+                &mut Cursor::new(Vec::new()),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+            let err = wrap_v1_with_options(
+                &mut car.as_slice(),
+                &mut Cursor::new(Vec::new()),
+                Default::default(),
+                CarReaderOptions::default(),
+            )
+            .await
+            .unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    CarConvertError::Decode(CarDecodeError::BlockDigestMismatch { .. })
+                ),
+                "{:?}",
+                err
+            );
+        })
+    }
+}
@@ -0,0 +1,471 @@
+use std::io;
+
+use crate::{
+    block_cid::assert_block_cid,
+    car_block::decode_block_prefix,
+    car_header::{
+        decode_car_header_start, decode_inner_car_header, CarHeader, CarHeaderStart, StreamEnd,
+    },
+    car_reader_options::CarReaderOptions,
+    carv2_header::{CarV2Header, CARV2_HEADER_SIZE, CARV2_PRAGMA_SIZE},
+    error::{CarDecodeError, Limit},
+    varint::U64_LEN,
+    Cid,
+};
+
+/// Output of [`CarDecoder::next_event()`]
+#[derive(Debug)]
+pub enum CarDecoderEvent {
+    /// The CAR header, always the first event
+    Header(CarHeader),
+    /// A block and its CID, in stream order
+    Block(Cid, Vec<u8>),
+    /// The buffered bytes are an incomplete header or section, push more with
+    /// [`CarDecoder::push()`]
+    NeedMoreData,
+    /// The CARv2 data payload ended. Bytes after it, padding and index, are not consumed
+    /// and dropped.
+    End,
+}
+
+/// Sans-IO decoder of a CAR stream. Bytes are pushed in chunks of any size and decoded
+/// into events, for sources that are not an `AsyncRead`. Supports CARv1 and CARv2 formats,
+/// same as [`crate::CarReader`].
+///
+/// - To decode pushed chunks [`CarDecoder::new()`]
+pub struct CarDecoder {
+    buf: Vec<u8>,
+    /// Position in `buf` of the first byte not consumed by an event
+    pos: usize,
+    consumed_bytes: u64,
+    state: DecoderState,
+    /// Bytes of the CARv2 padding still to drop from pushed chunks
+    skip_len: usize,
+    read_bytes: usize,
+    /// Ordinal of the next section
+    section_index: u64,
+    options: CarReaderOptions,
+}
+
+enum DecoderState {
+    Header,
+    /// After the CARv2 header, before the CARv1 header of the data payload
+    InnerHeader(CarV2Header),
+    Sections(StreamEnd),
+    /// The CARv2 data payload ended, later bytes are dropped
+    End,
+}
+
+impl CarDecoder {
+    /// Returns a decoder with no buffered bytes, expecting the start of a CAR stream
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::{CarDecoder, CarDecoderEvent};
+    ///
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let car = std::fs::read("./tests/custom_fixtures/helloworld.car")?;
+    ///   let mut decoder = CarDecoder::new(true);
+    ///
+    ///   for chunk in car.chunks(16) {
+    ///     decoder.push(chunk)?;
+    ///     loop {
+    ///       match decoder.next_event()? {
+    ///         CarDecoderEvent::Header(header) => println!("{:?}", header),
+    ///         CarDecoderEvent::Block(cid, block) => println!("{:?} {} bytes", cid, block.len()),
+    ///         CarDecoderEvent::NeedMoreData | CarDecoderEvent::End => break,
+    ///       }
+    ///     }
+    ///   }
+    ///
+    ///   decoder.finish()?;
+    ///   assert_eq!(decoder.consumed_bytes(), car.len() as u64);
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn new(validate_block_hash: bool) -> Self {
+        Self::with_options(CarReaderOptions {
+            validate_block_hash,
+            ..Default::default()
+        })
+    }
+
+    /// Same as [`CarDecoder::new()`] with custom limits and checks
+    pub fn with_options(options: CarReaderOptions) -> Self {
+        CarDecoder {
+            buf: vec![],
+            pos: 0,
+            consumed_bytes: 0,
+            state: DecoderState::Header,
+            skip_len: 0,
+            read_bytes: 0,
+            section_index: 0,
+            options,
+        }
+    }
+
+    /// Appends the next chunk of the CAR stream. The CARv2 padding is skipped as it is
+    /// pushed.
+    ///
+    /// Errors if the bytes not decoded yet would be more than the largest header or
+    /// section allowed by the options, and the chunk is not appended. Push chunks no
+    /// larger than that and call [`CarDecoder::next_event()`] until
+    /// [`CarDecoderEvent::NeedMoreData`] in between.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), CarDecodeError> {
+        if let DecoderState::End = self.state {
+            return Ok(());
+        }
+
+        let skipped = self.skip_len.min(chunk.len());
+        let buffered_len = (self.buffered_len() + chunk.len() - skipped) as u64;
+        let max_buffered_len = self.max_buffered_len();
+        if buffered_len > max_buffered_len {
+            return Err(CarDecodeError::LimitExceeded {
+                limit: Limit::BufferedLen,
+                value: buffered_len,
+                max: max_buffered_len,
+                location: None,
+            }
+            .located(self.consumed_bytes, None));
+        }
+
+        self.skip_len -= skipped;
+        self.consumed_bytes += skipped as u64;
+
+        // Drop consumed bytes before growing the buffer
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        self.buf.extend_from_slice(&chunk[skipped..]);
+        Ok(())
+    }
+
+    /// Decodes the next event from the buffered bytes. Returns
+    /// [`CarDecoderEvent::NeedMoreData`] until a full header or section is buffered.
+    ///
+    /// On error no bytes are consumed, so calling again returns the same error.
+    pub fn next_event(&mut self) -> Result<CarDecoderEvent, CarDecodeError> {
+        let eof_stream = match self.state {
+            DecoderState::Header => {
+                match decode_car_header_start(&self.buf[self.pos..], &self.options)? {
+                    Some(CarHeaderStart::V1(header, header_len)) => {
+                        self.state = DecoderState::Sections(header.eof_stream);
+                        self.consume(header_len);
+                        return Ok(CarDecoderEvent::Header(header));
+                    }
+                    Some(CarHeaderStart::V2 {
+                        header_v2,
+                        len,
+                        padding_len,
+                    }) => {
+                        self.state = DecoderState::InnerHeader(header_v2);
+                        self.consume(len);
+                        // Padding already pushed is dropped, the rest as it is pushed
+                        let buffered = padding_len.min(self.buffered_len());
+                        self.consume(buffered);
+                        self.skip_len = padding_len - buffered;
+                        return self.next_event();
+                    }
+                    None => return Ok(CarDecoderEvent::NeedMoreData),
+                }
+            }
+            DecoderState::InnerHeader(_) if self.skip_len > 0 => {
+                return Ok(CarDecoderEvent::NeedMoreData)
+            }
+            DecoderState::InnerHeader(header_v2) => {
+                match decode_inner_car_header(header_v2, &self.buf[self.pos..], &self.options)? {
+                    Some((header, header_len)) => {
+                        self.state = DecoderState::Sections(header.eof_stream);
+                        self.consume(header_len);
+                        return Ok(CarDecoderEvent::Header(header));
+                    }
+                    None => return Ok(CarDecoderEvent::NeedMoreData),
+                }
+            }
+            DecoderState::Sections(eof_stream) => eof_stream,
+            DecoderState::End => return Ok(CarDecoderEvent::End),
+        };
+
+        if eof_stream.is_reached(self.read_bytes)? {
+            self.state = DecoderState::End;
+            self.buf = vec![];
+            self.pos = 0;
+            return Ok(CarDecoderEvent::End);
+        }
+
+        let buf = &self.buf[self.pos..];
+        let locate =
+            |err: CarDecodeError| err.located(self.consumed_bytes, Some(self.section_index));
+        match decode_block_prefix(buf, self.consumed_bytes, &self.options).map_err(locate)? {
+            Some((cid, block, block_len, _)) => {
+                if self.options.validate_block_hash {
+                    assert_block_cid(&cid, &block, &self.options.verifiers).map_err(locate)?;
+                }
+                self.read_bytes += block_len;
+                self.section_index += 1;
+                self.consume(block_len);
+                Ok(CarDecoderEvent::Block(cid, block))
+            }
+            None => Ok(CarDecoderEvent::NeedMoreData),
+        }
+    }
+
+    /// Total byte length of the CAR stream consumed by the events returned so far
+    pub fn consumed_bytes(&self) -> u64 {
+        self.consumed_bytes
+    }
+
+    /// Byte length of the pushed bytes not consumed by any event yet
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// Checks that the CAR stream is complete, to call after the last chunk is pushed
+    /// and all its events are decoded. A CARv1 stream must end on a section boundary,
+    /// a CARv2 stream must include its full data payload.
+    pub fn finish(&self) -> Result<(), CarDecodeError> {
+        let (complete, section_index) = match self.state {
+            DecoderState::Header | DecoderState::InnerHeader(_) => (false, None),
+            DecoderState::Sections(StreamEnd::OnBlockEOF) => {
+                (self.buffered_len() == 0, Some(self.section_index))
+            }
+            DecoderState::Sections(eof_stream) => {
+                let reached = eof_stream.is_reached(self.read_bytes)?;
+                // Ended on a section boundary before the end of the CARv2 data payload
+                match eof_stream.block_start_eof() {
+                    Some(err) if !reached && self.buffered_len() == 0 => return Err(err),
+                    _ => (reached, Some(self.section_index)),
+                }
+            }
+            DecoderState::End => (true, None),
+        };
+
+        if complete {
+            Ok(())
+        } else {
+            Err(
+                CarDecodeError::from(io::Error::from(io::ErrorKind::UnexpectedEof))
+                    .located(self.consumed_bytes, section_index),
+            )
+        }
+    }
+
+    /// Max byte length of the header or section that [`CarDecoder::next_event()`] decodes
+    /// next, including its varint
+    fn max_buffered_len(&self) -> u64 {
+        let max_len = match self.state {
+            DecoderState::Sections(_) => self.options.max_block_len,
+            _ => self
+                .options
+                .max_header_len
+                .saturating_add((CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE) as u64),
+        };
+        max_len.saturating_add(U64_LEN as u64)
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.pos += len;
+        self.consumed_bytes += len as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor;
+
+    use super::*;
+    use crate::car_read_all;
+
+    /// Pushes `car` in chunks of `chunk_size` and collects all events
+    #[allow(clippy::type_complexity)]
+    fn decode_chunks(
+        car: &[u8],
+        chunk_size: usize,
+    ) -> Result<(CarDecoder, Option<CarHeader>, Vec<(Cid, Vec<u8>)>), CarDecodeError> {
+        let mut decoder = CarDecoder::new(true);
+        let mut header = None;
+        let mut blocks = vec![];
+
+        for chunk in car.chunks(chunk_size) {
+            decoder.push(chunk)?;
+            loop {
+                match decoder.next_event()? {
+                    CarDecoderEvent::Header(h) => header = Some(h),
+                    CarDecoderEvent::Block(cid, block) => blocks.push((cid, block)),
+                    CarDecoderEvent::NeedMoreData | CarDecoderEvent::End => break,
+                }
+            }
+        }
+
+        Ok((decoder, header, blocks))
+    }
+
+    #[test]
+    fn decoder_same_as_reader() {
+        for car_filepath in [
+            "./tests/spec_fixtures/carv1-basic.car",
+            "./tests/spec_fixtures/carv2-basic.car",
+            "./tests/go_car_fixtures/sample-v1.car",
+            "./tests/go_car_fixtures/sample-wrapped-v2.car",
+            "./tests/go_car_fixtures/sample-rw-bs-v2.car",
+        ] {
+            let car = std::fs::read(car_filepath).unwrap();
+            let (expected_blocks, expected_header) =
+                executor::block_on(car_read_all(&mut car.as_slice(), true)).unwrap();
+
+            // Header and sections split across chunks at every possible position
+            for chunk_size in [1, 7, 64, car.len()] {
+                let (decoder, header, blocks) = decode_chunks(&car, chunk_size).unwrap();
+                decoder.finish().unwrap();
+
+                let header = header.unwrap();
+                assert_eq!(header.roots, expected_header.roots, "{}", car_filepath);
+                assert_eq!(blocks, expected_blocks, "{} {}", car_filepath, chunk_size);
+
+                // Consumed up to the end of the data payload
+                let data_end = match &header.header_v2 {
+                    Some(header_v2) => header_v2.data_offset + header_v2.data_size,
+                    None => car.len() as u64,
+                };
+                // Padding and index after the data payload are dropped
+                assert_eq!(decoder.consumed_bytes(), data_end, "{}", car_filepath);
+                assert_eq!(decoder.buffered_len(), 0, "{}", car_filepath);
+            }
+        }
+    }
+
+    #[test]
+    fn decoder_consumed_bytes_per_event() {
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let mut decoder = CarDecoder::new(true);
+        decoder.push(&car).unwrap();
+
+        // 1 byte varint + 99 bytes header
+        assert!(matches!(
+            decoder.next_event().unwrap(),
+            CarDecoderEvent::Header(_)
+        ));
+        assert_eq!(decoder.consumed_bytes(), 100);
+
+        // Section of block 0, 1 byte varint + 91 bytes
+        assert!(matches!(
+            decoder.next_event().unwrap(),
+            CarDecoderEvent::Block(_, _)
+        ));
+        assert_eq!(decoder.consumed_bytes(), 100 + 92);
+    }
+
+    #[test]
+    fn decoder_need_more_data() {
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let mut decoder = CarDecoder::new(true);
+
+        assert!(matches!(
+            decoder.next_event().unwrap(),
+            CarDecoderEvent::NeedMoreData
+        ));
+
+        // Full header and a partial first section
+        decoder.push(&car[..150]).unwrap();
+        assert!(matches!(
+            decoder.next_event().unwrap(),
+            CarDecoderEvent::Header(_)
+        ));
+        assert!(matches!(
+            decoder.next_event().unwrap(),
+            CarDecoderEvent::NeedMoreData
+        ));
+        assert_eq!(decoder.consumed_bytes(), 100);
+        assert_eq!(decoder.buffered_len(), 50);
+
+        // Truncated
+        assert_eq!(
+            decoder.finish().unwrap_err().to_string(),
+            "io error: unexpected end of file in section 0 at byte 100"
+        );
+    }
+
+    #[test]
+    fn decoder_errors_same_as_reader() {
+        for car_filepath in [
+            "./tests/go_car_fixtures/sample-v1-with-zero-len-section.car",
+            "./tests/go_car_fixtures/sample-rootless-v42.car",
+        ] {
+            let car = std::fs::read(car_filepath).unwrap();
+            let expected_err =
+                executor::block_on(car_read_all(&mut car.as_slice(), true)).unwrap_err();
+
+            match decode_chunks(&car, 5) {
+                Err(err) => assert_eq!(err.to_string(), expected_err.to_string()),
+                Ok(_) => panic!("expected error for {}", car_filepath),
+            }
+        }
+    }
+
+    #[test]
+    fn decoder_skips_padding() {
+        // carv2-basic with 4096 bytes of padding, over the buffer bound of the options
+        let car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        let padding_len = 4096u64;
+        let mut padded = car[..51].to_vec();
+        padded[27..35].copy_from_slice(&(51 + padding_len).to_le_bytes());
+        padded[43..51].copy_from_slice(&(499 + padding_len).to_le_bytes());
+        padded.extend(vec![0; padding_len as usize]);
+        padded.extend(&car[51..]);
+
+        let options = CarReaderOptions {
+            max_header_len: 100,
+            ..Default::default()
+        };
+        let mut decoder = CarDecoder::with_options(options);
+        let mut blocks = 0;
+        for chunk in padded.chunks(64) {
+            decoder.push(chunk).unwrap();
+            loop {
+                match decoder.next_event().unwrap() {
+                    CarDecoderEvent::Block(_, _) => blocks += 1,
+                    CarDecoderEvent::Header(_) => {}
+                    CarDecoderEvent::NeedMoreData | CarDecoderEvent::End => break,
+                }
+            }
+            assert!(decoder.buffered_len() <= 100 + 51 + U64_LEN);
+        }
+
+        decoder.finish().unwrap();
+        assert_eq!(blocks, 5);
+        assert_eq!(decoder.consumed_bytes(), 499 + padding_len);
+    }
+
+    #[test]
+    fn decoder_push_limit() {
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let options = CarReaderOptions {
+            max_header_len: 100,
+            ..Default::default()
+        };
+        let mut decoder = CarDecoder::with_options(options);
+
+        // Pushed without decoding, 192 bytes over 100 + 51 + 10
+        for chunk in car.chunks(64).take(2) {
+            decoder.push(chunk).unwrap();
+        }
+        match decoder.push(&car[128..192]).unwrap_err() {
+            CarDecodeError::LimitExceeded {
+                limit: Limit::BufferedLen,
+                value: 192,
+                max: 161,
+                location: Some(location),
+            } => assert_eq!(location.offset, 0),
+            err => panic!("unexpected error {:?}", err),
+        }
+        assert_eq!(decoder.buffered_len(), 128);
+
+        // Same for a single chunk over the limit
+        let mut decoder = CarDecoder::with_options(CarReaderOptions {
+            max_header_len: 100,
+            ..Default::default()
+        });
+        assert!(decoder.push(&car[..162]).is_err());
+        assert_eq!(decoder.buffered_len(), 0);
+    }
+}
@@ -1,24 +1,62 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+};
+
 use futures::{AsyncRead, AsyncReadExt};
+use ipld_core::ipld::Ipld;
 
 use crate::{
+    car_reader_options::{CarReaderOptions, Strictness},
     carv1_header::{decode_carv1_header, CarV1Header},
-    carv2_header::{decode_carv2_header, CarV2Header, CARV2_HEADER_SIZE, CARV2_PRAGMA_SIZE},
-    error::CarDecodeError,
-    varint::read_varint_u64,
+    carv2_header::{
+        decode_carv2_header, CarV2Header, Characteristics, CARV2_HEADER_SIZE, CARV2_PRAGMA_SIZE,
+    },
+    error::{CarDecodeError, CarV1HeaderError, CarV2HeaderError, Limit, SpecViolation},
+    varint::{check_minimal_encoding, decode_varint_u64, read_varint_u64, varint_len_u64, U64_LEN},
     Cid,
 };
 
-/// Arbitrary high value to prevent big allocations
-const MAX_HEADER_LEN: u64 = 1048576;
-/// Arbitrary high value to prevent big allocations
-const MAX_PADDING_LEN: usize = 1073741824;
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub(crate) enum StreamEnd {
-    AfterNBytes(usize),
+    /// After `blocks_len` bytes of sections, the end of the data payload of `header_v2`
+    AfterNBytes {
+        blocks_len: usize,
+        header_v2: CarV2Header,
+    },
     OnBlockEOF,
 }
 
+impl StreamEnd {
+    /// Returns true if no more blocks must be read after `read_bytes` of blocks. Errors if
+    /// the last section ends past the CARv2 data payload, as its data size is wrong.
+    pub(crate) fn is_reached(&self, read_bytes: usize) -> Result<bool, CarDecodeError> {
+        match self {
+            StreamEnd::AfterNBytes {
+                blocks_len,
+                header_v2,
+            } if read_bytes > *blocks_len => Err(invalid_header_v2(
+                header_v2,
+                CarV2HeaderError::DataEndInsideSection,
+            )),
+            StreamEnd::AfterNBytes { blocks_len, .. } => Ok(read_bytes == *blocks_len),
+            StreamEnd::OnBlockEOF => Ok(false),
+        }
+    }
+
+    /// Error of an EOF at the start of a section before the end is reached, `None` for the
+    /// clean end of a CARv1. A CARv2 stream ending there is shorter than its data size.
+    pub(crate) fn block_start_eof(&self) -> Option<CarDecodeError> {
+        match self {
+            StreamEnd::AfterNBytes { header_v2, .. } => Some(invalid_header_v2(
+                header_v2,
+                CarV2HeaderError::DataEndPastStream,
+            )),
+            StreamEnd::OnBlockEOF => None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum CarVersion {
     V1 = 1,
@@ -29,64 +67,306 @@ pub enum CarVersion {
 pub struct CarHeader {
     pub version: CarVersion,
     pub roots: Vec<Cid>,
+    /// Raw bits of [`CarV2Header::characteristics`], `None` for a CARv1
     pub characteristics_v2: Option<u128>,
+    /// Header of a CARv2 after its pragma, `None` for a CARv1
+    pub header_v2: Option<CarV2Header>,
+    /// Keys of the CARv1 header other than `roots` and `version`, as written by
+    /// [`crate::CarWriter::with_extra()`]
+    pub extra: BTreeMap<String, Ipld>,
     pub(crate) eof_stream: StreamEnd,
+    /// Byte length of the CARv1 header including varint, the first section
+    /// is at this offset from the start of the CARv1 data payload
+    pub(crate) header_v1_len: usize,
 }
 
-impl CarHeader {}
+impl CarHeader {
+    /// Absolute byte offset of the CARv1 header, from the start of the CAR stream
+    pub(crate) fn header_v1_offset(&self) -> u64 {
+        self.header_v2.as_ref().map_or(0, |h| h.data_offset)
+    }
+
+    /// Absolute byte offset of the first section, from the start of the CAR stream
+    pub(crate) fn sections_offset(&self) -> u64 {
+        self.header_v1_offset() + self.header_v1_len as u64
+    }
+}
 
 pub(crate) async fn read_car_header<R: AsyncRead + Unpin>(
     r: &mut R,
+    options: &CarReaderOptions,
 ) -> Result<CarHeader, CarDecodeError> {
-    let (header, _) = read_carv1_header(r).await?;
+    let (header, header_len) = read_carv1_header(r, options).await.map_err(at_header(0))?;
+
+    let header = match header.version {
+        1 => car_header_v1(header, header_len, options)?,
+        2 => {
+            check_header_keys(&header, options).map_err(at_header(0))?;
+            let (header_v2, (header_v1, header_v1_len)) = read_carv2_header(r, options).await?;
+            car_header_v2(header_v2, header_v1, header_v1_len, options)?
+        }
+        _ => {
+            return Err(at_header(0)(CarDecodeError::UnsupportedCarVersion {
+                version: header.version,
+                location: None,
+            }))
+        }
+    };
+
+    check_car_header(&header, options)?;
+    Ok(header)
+}
+
+/// Start of a CAR header decoded by [`decode_car_header_start`]
+pub(crate) enum CarHeaderStart {
+    /// A full CARv1 header and its byte length including varint
+    V1(CarHeader, usize),
+    /// The CARv2 pragma and header of `len` bytes. The CARv1 header follows after
+    /// `padding_len` bytes of padding.
+    V2 {
+        header_v2: CarV2Header,
+        len: usize,
+        padding_len: usize,
+    },
+}
+
+/// Decodes the start of a CAR header from the start of `buf`, same as
+/// [`read_car_header`] up to the CARv2 padding. Returns `None` if `buf` ends before it.
+/// The CARv1 header of a CARv2 is decoded by [`decode_inner_car_header`] after the
+/// padding, which is not buffered.
+pub(crate) fn decode_car_header_start(
+    buf: &[u8],
+    options: &CarReaderOptions,
+) -> Result<Option<CarHeaderStart>, CarDecodeError> {
+    let (header, header_len) =
+        match decode_carv1_header_prefix(buf, options).map_err(at_header(0))? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
 
     match header.version {
-        1 => Ok(CarHeader {
-            version: CarVersion::V1,
-            roots: header.roots.ok_or(CarDecodeError::InvalidCarV1Header(
-                "v1 header has not roots".to_owned(),
-            ))?,
-            characteristics_v2: None,
-            eof_stream: StreamEnd::OnBlockEOF,
-        }),
+        1 => {
+            let header = car_header_v1(header, header_len, options)?;
+            check_car_header(&header, options)?;
+            Ok(Some(CarHeaderStart::V1(header, header_len)))
+        }
         2 => {
-            let (header_v2, (header_v1, header_v1_len)) = read_carv2_header(r).await?;
-            let blocks_len = header_v2.data_size as usize - header_v1_len;
-            Ok(CarHeader {
-                version: CarVersion::V2,
-                roots: header_v1.roots.ok_or(CarDecodeError::InvalidCarV1Header(
-                    "v1 header has not roots".to_owned(),
-                ))?,
-                characteristics_v2: Some(header_v2.characteristics),
-                eof_stream: StreamEnd::AfterNBytes(blocks_len),
-            })
+            check_header_keys(&header, options).map_err(at_header(0))?;
+            let header_v2 = match buf[header_len..].get(..CARV2_HEADER_SIZE) {
+                // unwrap: slice is exactly CARV2_HEADER_SIZE bytes
+                Some(header_v2_buf) => decode_carv2_header(header_v2_buf.try_into().unwrap())
+                    .and_then(|header_v2| {
+                        check_carv2_header(&header_v2, options).map(|()| header_v2)
+                    })
+                    .map_err(at_header(CARV2_PRAGMA_SIZE as u64))?,
+                None => return Ok(None),
+            };
+            let padding_len = carv2_padding_len(&header_v2, options)
+                .map_err(at_header((CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE) as u64))?;
+
+            Ok(Some(CarHeaderStart::V2 {
+                header_v2,
+                len: header_len + CARV2_HEADER_SIZE,
+                padding_len,
+            }))
         }
-        _ => Err(CarDecodeError::UnsupportedCarVersion {
+        _ => Err(at_header(0)(CarDecodeError::UnsupportedCarVersion {
             version: header.version,
-        }),
+            location: None,
+        })),
+    }
+}
+
+/// Decodes the CARv1 header of a CARv2 with `header_v2` from the start of `buf`, after
+/// [`decode_car_header_start`] and the padding. Returns `None` if `buf` ends before it.
+///
+/// # Returns
+///
+/// (header, byte length of the CARv1 header including varint)
+pub(crate) fn decode_inner_car_header(
+    header_v2: CarV2Header,
+    buf: &[u8],
+    options: &CarReaderOptions,
+) -> Result<Option<(CarHeader, usize)>, CarDecodeError> {
+    let (header_v1, header_v1_len) =
+        match decode_carv1_header_prefix(buf, options).map_err(at_header(header_v2.data_offset))? {
+            Some(header_v1) => header_v1,
+            None => return Ok(None),
+        };
+
+    let header = car_header_v2(header_v2, header_v1, header_v1_len, options)?;
+    check_car_header(&header, options)?;
+    Ok(Some((header, header_v1_len)))
+}
+
+fn car_header_v1(
+    header: CarV1Header,
+    header_len: usize,
+    options: &CarReaderOptions,
+) -> Result<CarHeader, CarDecodeError> {
+    check_header_keys(&header, options).map_err(at_header(0))?;
+    Ok(CarHeader {
+        version: CarVersion::V1,
+        roots: header
+            .roots
+            .ok_or(CarV1HeaderError::MissingRoots)
+            .map_err(at_header(0))?,
+        characteristics_v2: None,
+        extra: header.extra,
+        eof_stream: StreamEnd::OnBlockEOF,
+        header_v2: None,
+        header_v1_len: header_len,
+    })
+}
+
+fn car_header_v2(
+    header_v2: CarV2Header,
+    header_v1: CarV1Header,
+    header_v1_len: usize,
+    options: &CarReaderOptions,
+) -> Result<CarHeader, CarDecodeError> {
+    check_inner_header(&header_v1, options).map_err(at_header(header_v2.data_offset))?;
+    let blocks_len = header_v2
+        .data_size
+        .checked_sub(header_v1_len as u64)
+        .ok_or_else(|| {
+            invalid_header_v2(
+                &header_v2,
+                CarV2HeaderError::DataEndInsideHeader {
+                    header_v1_len: header_v1_len as u64,
+                },
+            )
+        })?;
+    let blocks_len = usize::try_from(blocks_len).map_err(|_| {
+        CarDecodeError::LimitExceeded {
+            limit: Limit::DataSize,
+            value: blocks_len,
+            max: usize::MAX as u64,
+            location: None,
+        }
+        .located(CARV2_PRAGMA_SIZE as u64, None)
+    })?;
+    Ok(CarHeader {
+        version: CarVersion::V2,
+        roots: header_v1
+            .roots
+            .ok_or(CarV1HeaderError::MissingRoots)
+            .map_err(at_header(header_v2.data_offset))?,
+        characteristics_v2: Some(header_v2.characteristics.bits()),
+        extra: header_v1.extra,
+        eof_stream: StreamEnd::AfterNBytes {
+            blocks_len,
+            header_v2,
+        },
+        header_v2: Some(header_v2),
+        header_v1_len,
+    })
+}
+
+/// Limits and roots of a decoded header that do not depend on its byte length
+fn check_car_header(header: &CarHeader, options: &CarReaderOptions) -> Result<(), CarDecodeError> {
+    check_car_header_limits(header, options)
+        .and_then(|()| check_car_header_roots(header, options))
+        .map_err(at_header(header.header_v1_offset()))
+}
+
+/// Rejects keys that the specs do not define in a CARv1 header or a CARv2 pragma
+fn check_header_keys(
+    header: &CarV1Header,
+    options: &CarReaderOptions,
+) -> Result<(), CarDecodeError> {
+    if options.strictness == Strictness::Lenient {
+        return Ok(());
+    }
+
+    // The CARv2 pragma only has a version
+    let unknown_key = match header.roots {
+        Some(_) if header.version == 2 => Some("roots"),
+        _ => header.extra.keys().next().map(String::as_str),
+    };
+    match unknown_key {
+        Some(key) => Err(CarDecodeError::from(SpecViolation::UnknownHeaderKey {
+            key: key.to_owned(),
+        })),
+        None => Ok(()),
     }
 }
 
+/// Rejects a CARv1 header inside a CARv2 that is not a plain CARv1 header
+fn check_inner_header(
+    header_v1: &CarV1Header,
+    options: &CarReaderOptions,
+) -> Result<(), CarDecodeError> {
+    if options.strictness == Strictness::Strict && header_v1.version != 1 {
+        return Err(CarDecodeError::from(SpecViolation::InnerHeaderVersion {
+            version: header_v1.version,
+        }));
+    }
+    check_header_keys(header_v1, options)
+}
+
+fn check_car_header_roots(
+    header: &CarHeader,
+    options: &CarReaderOptions,
+) -> Result<(), CarDecodeError> {
+    if options.strictness == Strictness::Lenient {
+        return Ok(());
+    }
+
+    if header.roots.is_empty() {
+        return Err(CarDecodeError::from(SpecViolation::EmptyRoots));
+    }
+
+    let mut roots = HashSet::with_capacity(header.roots.len());
+    if let Some(cid) = header.roots.iter().find(|cid| !roots.insert(*cid)) {
+        return Err(CarDecodeError::from(SpecViolation::DuplicateRoot {
+            cid: *cid,
+        }));
+    }
+
+    Ok(())
+}
+
+fn check_car_header_limits(
+    header: &CarHeader,
+    options: &CarReaderOptions,
+) -> Result<(), CarDecodeError> {
+    if header.roots.len() > options.max_roots {
+        return Err(CarDecodeError::LimitExceeded {
+            limit: Limit::Roots,
+            value: header.roots.len() as u64,
+            max: options.max_roots as u64,
+            location: None,
+        });
+    }
+
+    if let Some(max_total_bytes) = options.max_total_bytes {
+        if header.sections_offset() > max_total_bytes {
+            return Err(CarDecodeError::LimitExceeded {
+                limit: Limit::TotalBytes,
+                value: header.sections_offset(),
+                max: max_total_bytes,
+                location: None,
+            });
+        }
+    }
+
+    Ok(())
+}
+
 /// # Returns
 ///
 /// (header, total header byte length including varint)
 async fn read_carv1_header<R: AsyncRead + Unpin>(
     src: &mut R,
+    options: &CarReaderOptions,
 ) -> Result<(CarV1Header, usize), CarDecodeError> {
     // Decode header varint
-    let (header_len, varint_len) =
-        read_varint_u64(src)
-            .await?
-            .ok_or(CarDecodeError::InvalidCarV1Header(
-                "invalid header varint".to_string(),
-            ))?;
-
-    if header_len > MAX_HEADER_LEN {
-        return Err(CarDecodeError::InvalidCarV1Header(format!(
-            "header len too big {}",
-            header_len
-        )));
-    }
+    let (header_len, varint_len) = read_varint_u64(src)
+        .await?
+        .ok_or(CarV1HeaderError::InvalidVarint)?;
+
+    check_carv1_header_len(header_len, varint_len, options)?;
 
     let mut header_buf = vec![0u8; header_len as usize];
     src.read_exact(&mut header_buf).await?;
@@ -96,58 +376,508 @@ async fn read_carv1_header<R: AsyncRead + Unpin>(
     Ok((header, header_len as usize + varint_len))
 }
 
+/// Same as [`read_carv1_header`] from the start of `buf`. Returns `None` if `buf` ends
+/// before the header does.
+fn decode_carv1_header_prefix(
+    buf: &[u8],
+    options: &CarReaderOptions,
+) -> Result<Option<(CarV1Header, usize)>, CarDecodeError> {
+    let (header_len, varint_len) = match decode_varint_u64(buf) {
+        Some(varint) => varint,
+        None if buf.len() < U64_LEN => return Ok(None),
+        None => return Err(CarV1HeaderError::InvalidVarint.into()),
+    };
+
+    check_carv1_header_len(header_len, varint_len, options)?;
+
+    let header_end = varint_len + header_len as usize;
+    match buf.get(varint_len..header_end) {
+        Some(header_buf) => Ok(Some((decode_carv1_header(header_buf)?, header_end))),
+        None => Ok(None),
+    }
+}
+
+fn check_carv1_header_len(
+    header_len: u64,
+    varint_len: usize,
+    options: &CarReaderOptions,
+) -> Result<(), CarDecodeError> {
+    check_minimal_encoding(varint_len, varint_len_u64(header_len), options.strictness)?;
+    if header_len > options.max_header_len {
+        return Err(CarDecodeError::LimitExceeded {
+            limit: Limit::HeaderLen,
+            value: header_len,
+            max: options.max_header_len,
+            location: None,
+        });
+    }
+    Ok(())
+}
+
 async fn read_carv2_header<R: AsyncRead + Unpin>(
     r: &mut R,
+    options: &CarReaderOptions,
 ) -> Result<(CarV2Header, (CarV1Header, usize)), CarDecodeError> {
+    let header_v2_offset = CARV2_PRAGMA_SIZE as u64;
     let mut header_buf = [0u8; CARV2_HEADER_SIZE];
-    r.read_exact(&mut header_buf).await?;
+    r.read_exact(&mut header_buf)
+        .await
+        .map_err(at_header(header_v2_offset))?;
 
-    let header_v2 = decode_carv2_header(&header_buf)?;
+    let header_v2 = decode_carv2_header(&header_buf).map_err(at_header(header_v2_offset))?;
+    check_carv2_header(&header_v2, options).map_err(at_header(header_v2_offset))?;
 
     // Read padding, and throw away
-    let padding_len = header_v2.data_offset as usize - CARV2_PRAGMA_SIZE - CARV2_HEADER_SIZE;
-    if padding_len > 0 {
-        if padding_len > MAX_PADDING_LEN {
-            return Err(CarDecodeError::InvalidCarV1Header(format!(
-                "padding len too big {}",
-                padding_len
-            )));
-        }
-        let mut padding_buf = vec![0u8; padding_len];
-        r.read_exact(&mut padding_buf).await?;
+    let padding_offset = (CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE) as u64;
+    let padding_len = carv2_padding_len(&header_v2, options).map_err(at_header(padding_offset))?;
+    let skipped = futures::io::copy(r.take(padding_len as u64), &mut futures::io::sink())
+        .await
+        .map_err(at_header(padding_offset))?;
+    if skipped < padding_len as u64 {
+        return Err(at_header(padding_offset)(io::Error::from(
+            io::ErrorKind::UnexpectedEof,
+        )));
     }
 
     // Read inner CARv1 header
-    let header_v1 = read_carv1_header(r).await?;
+    let header_v1 = read_carv1_header(r, options)
+        .await
+        .map_err(at_header(header_v2.data_offset))?;
 
     Ok((header_v2, header_v1))
 }
 
+/// Rejects CARv2 headers whose data payload and index can not be laid out as
+/// `[pragma][v2 header][opt padding][CARv1][opt padding][opt index]`
+fn check_carv2_header(
+    header_v2: &CarV2Header,
+    options: &CarReaderOptions,
+) -> Result<(), CarDecodeError> {
+    let header_end = (CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE) as u64;
+    let CarV2Header {
+        data_offset,
+        data_size,
+        index_offset,
+        ..
+    } = *header_v2;
+
+    let unknown_bits = header_v2.characteristics.bits() & !Characteristics::FULLY_INDEXED.bits();
+    if options.strictness == Strictness::Strict && unknown_bits != 0 {
+        return Err(SpecViolation::UnknownCharacteristics {
+            bits: unknown_bits.to_be_bytes(),
+        }
+        .into());
+    }
+
+    // Same error in both modes, the data payload can not be located
+    if data_offset < header_end {
+        return Err(invalid_header_v2(
+            header_v2,
+            CarV2HeaderError::DataOffsetBeforeHeaderEnd,
+        ));
+    }
+
+    let data_end = data_offset
+        .checked_add(data_size)
+        .ok_or_else(|| invalid_header_v2(header_v2, CarV2HeaderError::DataEndOverflow))?;
+
+    // 0 for no index
+    let reason = if index_offset == 0 {
+        return Ok(());
+    } else if index_offset < header_end {
+        CarV2HeaderError::IndexOffsetBeforeHeaderEnd
+    } else if index_offset < data_offset {
+        // The index runs to the end of the stream, over the data payload
+        CarV2HeaderError::IndexOverlapsData
+    } else if index_offset < data_end {
+        CarV2HeaderError::IndexInsideData
+    } else {
+        return Ok(());
+    };
+    Err(invalid_header_v2(header_v2, reason))
+}
+
+/// Geometry error of `header_v2`, located at the CARv2 header
+fn invalid_header_v2(header_v2: &CarV2Header, reason: CarV2HeaderError) -> CarDecodeError {
+    at_header(CARV2_PRAGMA_SIZE as u64)(CarDecodeError::InvalidCarV2Header {
+        reason,
+        data_offset: header_v2.data_offset,
+        data_size: header_v2.data_size,
+        index_offset: header_v2.index_offset,
+        location: None,
+    })
+}
+
+/// Byte length of the padding between the CARv2 header and the CARv1 data payload
+fn carv2_padding_len(
+    header_v2: &CarV2Header,
+    options: &CarReaderOptions,
+) -> Result<usize, CarDecodeError> {
+    // Not below the header end after check_carv2_header
+    let header_end = (CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE) as u64;
+    let padding_len = header_v2.data_offset.saturating_sub(header_end);
+    let max = options.max_padding_len.min(usize::MAX as u64);
+    if padding_len > max {
+        return Err(CarDecodeError::LimitExceeded {
+            limit: Limit::PaddingLen,
+            value: padding_len,
+            max,
+            location: None,
+        });
+    }
+    Ok(padding_len as usize)
+}
+
+/// Locates an error of the header part at absolute byte `offset` of the CAR stream
+fn at_header<E: Into<CarDecodeError>>(offset: u64) -> impl FnOnce(E) -> CarDecodeError {
+    move |err| err.into().located(offset, None)
+}
+
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use futures::{executor, io::Cursor};
+    use ipld_core::{codec::Codec, ipld::Ipld};
+    use serde_ipld_dagcbor::codec::DagCborCodec;
 
     use super::*;
     use crate::{
         carv1_header::CarV1Header,
         carv2_header::{CARV2_PRAGMA, CARV2_PRAGMA_SIZE},
+        varint::encode_varint_u64,
     };
 
+    const ROOT: &str = "bafyreihyrpefhacm6kkp4ql6j6udakdit7g3dmkzfriqfykhjw6cad5lrm";
+
+    /// Decodes the header of `car` with both the reader and the buffer decoder, which must
+    /// agree
+    fn read_header(car: &[u8], strictness: Strictness) -> Result<CarHeader, CarDecodeError> {
+        let options = CarReaderOptions {
+            strictness,
+            ..Default::default()
+        };
+        let read = executor::block_on(read_car_header(&mut &car[..], &options));
+        let mut decoder = crate::CarDecoder::with_options(options);
+        let decoded = decoder.push(car).and_then(|_| match decoder.next_event()? {
+            crate::CarDecoderEvent::Header(header) => Ok(header),
+            event => panic!("decoded {:?}", event),
+        });
+        match (read, decoded) {
+            (Ok(header), Ok(_)) => Ok(header),
+            (Err(err), Err(decode_err)) => {
+                assert_eq!(err.to_string(), decode_err.to_string());
+                Err(err)
+            }
+            (read, decoded) => panic!("read {:?} but decoded {:?}", read, decoded),
+        }
+    }
+
+    /// Asserts that `car` is only rejected by strict mode with `violation` at `offset`
+    fn assert_spec_violation(car: &[u8], violation: SpecViolation, offset: u64) {
+        read_header(car, Strictness::Lenient).unwrap();
+        let err = read_header(car, Strictness::Strict).unwrap_err();
+        assert_eq!((err.offset(), err.section_index()), (Some(offset), None));
+        match err {
+            CarDecodeError::SpecViolation { violation: err, .. } => assert_eq!(err, violation),
+            err => panic!("expected SpecViolation but got {:?}", err),
+        }
+    }
+
+    fn header_ipld(version: u64, roots: &[Cid], extra: &[(&str, Ipld)]) -> Ipld {
+        let mut map: BTreeMap<String, Ipld> = extra
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+        map.insert(
+            "roots".to_string(),
+            Ipld::List(roots.iter().map(|root| Ipld::Link(*root)).collect()),
+        );
+        map.insert("version".to_string(), Ipld::Integer(version.into()));
+        Ipld::Map(map)
+    }
+
+    /// Header of `header` with its varint
+    fn carv1_header_bytes(header: &Ipld) -> Vec<u8> {
+        let header = DagCborCodec::encode_to_vec(header).unwrap();
+        let mut buf = encode_varint_u64(header.len() as u64, &mut [0; U64_LEN]).to_vec();
+        buf.extend(header);
+        buf
+    }
+
+    /// CARv2 without sections nor index, with `header_v1` as its CARv1 header
+    fn carv2_header_bytes(header_v1: &Ipld) -> Vec<u8> {
+        let header_v1 = carv1_header_bytes(header_v1);
+        let mut car = CARV2_PRAGMA.to_vec();
+        car.extend(0u128.to_be_bytes());
+        car.extend(((CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE) as u64).to_le_bytes());
+        car.extend((header_v1.len() as u64).to_le_bytes());
+        car.extend(0u64.to_le_bytes());
+        car.extend(header_v1);
+        car
+    }
+
     #[test]
     fn read_carv1_header_v2_pragma() {
         executor::block_on(async {
             assert_eq!(
-                read_carv1_header(&mut Cursor::new(&CARV2_PRAGMA))
-                    .await
-                    .unwrap(),
+                read_carv1_header(
+                    &mut Cursor::new(&CARV2_PRAGMA),
+                    &CarReaderOptions::default()
+                )
+                .await
+                .unwrap(),
                 (
                     CarV1Header {
                         version: 2,
-                        roots: None
+                        roots: None,
+                        ..Default::default()
                     },
                     CARV2_PRAGMA_SIZE
                 )
             )
         })
     }
+
+    #[test]
+    fn carv2_header_locations() {
+        let car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        let header = read_header(&car, Strictness::Lenient).unwrap();
+        assert_eq!(
+            header.header_v2,
+            Some(CarV2Header {
+                characteristics: Characteristics::empty(),
+                data_offset: 51,
+                data_size: 448,
+                index_offset: 499,
+            })
+        );
+        assert_eq!(header.characteristics_v2, Some(0));
+
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let header = read_header(&car, Strictness::Lenient).unwrap();
+        assert_eq!(header.header_v2, None);
+    }
+
+    #[test]
+    fn carv2_header_geometry() {
+        let car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        // data_offset 51, data_size 448 and index_offset 499 at bytes 27, 35 and 43
+        let with_header = |data_offset: u64, data_size: u64, index_offset: u64| {
+            let mut car = car.clone();
+            car[27..35].copy_from_slice(&data_offset.to_le_bytes());
+            car[35..43].copy_from_slice(&data_size.to_le_bytes());
+            car[43..51].copy_from_slice(&index_offset.to_le_bytes());
+            car
+        };
+
+        for ((data_offset, data_size, index_offset), expected) in [
+            ((51, 448, 0), None),
+            (
+                (51, 448, 20),
+                Some(CarV2HeaderError::IndexOffsetBeforeHeaderEnd),
+            ),
+            ((100, 448, 60), Some(CarV2HeaderError::IndexOverlapsData)),
+            ((51, 448, 300), Some(CarV2HeaderError::IndexInsideData)),
+            ((51, 449, 499), Some(CarV2HeaderError::IndexInsideData)),
+            (
+                (u64::MAX - 10, 448, 0),
+                Some(CarV2HeaderError::DataEndOverflow),
+            ),
+            (
+                (51, 50, 0),
+                Some(CarV2HeaderError::DataEndInsideHeader { header_v1_len: 57 }),
+            ),
+        ] {
+            let car = with_header(data_offset, data_size, index_offset);
+            match (read_header(&car, Strictness::Lenient), expected) {
+                (Ok(_), None) => {}
+                (Err(err), Some(expected)) => {
+                    assert_eq!(err.offset(), Some(11));
+                    assert_invalid_header_v2(err, expected, (data_offset, data_size, index_offset));
+                }
+                (result, _) => panic!("unexpected {:?} for {:?}", result, expected),
+            }
+        }
+    }
+
+    /// Asserts that `err` is an [`CarDecodeError::InvalidCarV2Header`] of `expected`
+    /// with header (data_offset, data_size, index_offset)
+    fn assert_invalid_header_v2(
+        err: CarDecodeError,
+        expected: CarV2HeaderError,
+        header: (u64, u64, u64),
+    ) {
+        match err {
+            CarDecodeError::InvalidCarV2Header {
+                reason,
+                data_offset,
+                data_size,
+                index_offset,
+                ..
+            } => {
+                assert_eq!(reason, expected);
+                assert_eq!((data_offset, data_size, index_offset), header);
+            }
+            err => panic!("expected InvalidCarV2Header but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn carv2_data_size_inside_last_section() {
+        let mut car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        // data_size at byte 35, 1 byte short of the last section end
+        car[35..43].copy_from_slice(&447u64.to_le_bytes());
+
+        let err = executor::block_on(crate::car_read_all(&mut car.as_slice(), true)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid CARv2 header: data payload ends inside the last section (data offset 51, data size 447, index offset 499) at byte 11"
+        );
+        assert_invalid_header_v2(err, CarV2HeaderError::DataEndInsideSection, (51, 447, 499));
+    }
+
+    #[test]
+    fn carv2_data_size_past_stream_end() {
+        let car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        // Stream cut at the data payload end 499, data_size 500 at byte 35
+        let mut car = car[..499].to_vec();
+        car[35..43].copy_from_slice(&500u64.to_le_bytes());
+        car[43..51].copy_from_slice(&0u64.to_le_bytes());
+
+        let err = executor::block_on(crate::car_read_all(&mut car.as_slice(), true)).unwrap_err();
+        assert_eq!(err.offset(), Some(11));
+        assert_invalid_header_v2(err, CarV2HeaderError::DataEndPastStream, (51, 500, 0));
+
+        let mut decoder = crate::CarDecoder::new(true);
+        decoder.push(&car).unwrap();
+        while !matches!(
+            decoder.next_event().unwrap(),
+            crate::CarDecoderEvent::NeedMoreData
+        ) {}
+        assert_invalid_header_v2(
+            decoder.finish().unwrap_err(),
+            CarV2HeaderError::DataEndPastStream,
+            (51, 500, 0),
+        );
+    }
+
+    #[test]
+    fn strict_non_minimal_header_varint() {
+        // carv1-basic header is 99 bytes, a 1 byte varint
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let car = [&[0xe3, 0x00], &car[1..]].concat();
+        assert_spec_violation(
+            &car,
+            SpecViolation::NonMinimalVarint {
+                len: 2,
+                minimal_len: 1,
+            },
+            0,
+        );
+    }
+
+    #[test]
+    fn strict_unknown_header_key() {
+        let root = Cid::try_from(ROOT).unwrap();
+        let header = header_ipld(1, &[root], &[("extra", Ipld::Bool(true))]);
+        assert_spec_violation(
+            &carv1_header_bytes(&header),
+            SpecViolation::UnknownHeaderKey {
+                key: "extra".to_string(),
+            },
+            0,
+        );
+    }
+
+    #[test]
+    fn strict_empty_roots() {
+        assert_spec_violation(
+            &carv1_header_bytes(&header_ipld(1, &[], &[])),
+            SpecViolation::EmptyRoots,
+            0,
+        );
+    }
+
+    #[test]
+    fn strict_duplicate_root() {
+        let root = Cid::try_from(ROOT).unwrap();
+        assert_spec_violation(
+            &carv1_header_bytes(&header_ipld(1, &[root, root], &[])),
+            SpecViolation::DuplicateRoot { cid: root },
+            0,
+        );
+    }
+
+    #[test]
+    fn strict_carv2_pragma_roots() {
+        let root = Cid::try_from(ROOT).unwrap();
+        let car = carv2_header_bytes(&header_ipld(1, &[root], &[]));
+        let pragma = carv1_header_bytes(&header_ipld(2, &[], &[]));
+        let err = read_header(
+            &[&pragma[..], &car[CARV2_PRAGMA_SIZE..]].concat(),
+            Strictness::Strict,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "spec violation: unknown header key \"roots\" at byte 0"
+        );
+    }
+
+    #[test]
+    fn strict_inner_header() {
+        let root = Cid::try_from(ROOT).unwrap();
+        read_header(
+            &carv2_header_bytes(&header_ipld(1, &[root], &[])),
+            Strictness::Strict,
+        )
+        .unwrap();
+
+        assert_spec_violation(
+            &carv2_header_bytes(&header_ipld(2, &[root], &[])),
+            SpecViolation::InnerHeaderVersion { version: 2 },
+            51,
+        );
+        assert_spec_violation(
+            &carv2_header_bytes(&header_ipld(1, &[root], &[("extra", Ipld::Null)])),
+            SpecViolation::UnknownHeaderKey {
+                key: "extra".to_string(),
+            },
+            51,
+        );
+    }
+
+    #[test]
+    fn data_offset_before_header_end_both_modes() {
+        let mut car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        // data_offset at byte 11 + 16
+        car[27..35].copy_from_slice(&10u64.to_le_bytes());
+
+        for strictness in [Strictness::Lenient, Strictness::Strict] {
+            let err = read_header(&car, strictness).unwrap_err();
+            assert_invalid_header_v2(
+                err,
+                CarV2HeaderError::DataOffsetBeforeHeaderEnd,
+                (10, 448, 499),
+            );
+        }
+    }
+
+    #[test]
+    fn strict_unknown_characteristics() {
+        let mut car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        // Characteristics u128 BE at byte 11, fully indexed is known
+        car[11] = 0x80;
+        read_header(&car, Strictness::Strict).unwrap();
+
+        car[26] = 0x01;
+        assert_spec_violation(
+            &car,
+            SpecViolation::UnknownCharacteristics {
+                bits: 1u128.to_be_bytes(),
+            },
+            11,
+        );
+    }
 }
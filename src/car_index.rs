@@ -0,0 +1,585 @@
+use std::collections::BTreeMap;
+
+use futures::{AsyncRead, AsyncReadExt};
+use ipld_core::cid::multihash::Multihash;
+
+use crate::{
+    block_cid::CODE_IDENTITY,
+    error::{CarDecodeError, IndexError, Limit},
+    varint::{decode_varint_u64, encode_varint_u64, U64_LEN},
+    Cid,
+};
+
+/// Multicodec code of the CARv2 IndexSorted format
+pub(crate) const CODE_INDEX_SORTED: u64 = 0x0400;
+/// Multicodec code of the CARv2 MultihashIndexSorted format
+pub(crate) const CODE_MULTIHASH_INDEX_SORTED: u64 = 0x0401;
+
+/// Smallest valid bucket width, an empty digest plus its offset
+const MIN_WIDTH: u32 = 8;
+
+/// CARv2 index format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CarIndexCodec {
+    /// IndexSorted 0x0400, see [`IndexSorted`]
+    #[default]
+    Sorted,
+    /// MultihashIndexSorted 0x0401, see [`MultihashIndexSorted`]
+    MultihashSorted,
+}
+
+/// Collects the section offsets of a CARv1 data payload to build a [`CarIndex`]
+#[derive(Debug, Default)]
+pub(crate) struct CarIndexBuilder {
+    entries: Vec<(Multihash<64>, u64)>,
+}
+
+impl CarIndexBuilder {
+    /// Records the section of `cid` at `offset` relative to the start of the CARv1 data
+    /// payload. Identity CIDs are not indexed, since their data is in the CID itself.
+    pub(crate) fn insert(&mut self, cid: &Cid, offset: u64) {
+        if cid.hash().code() != CODE_IDENTITY {
+            self.entries.push((*cid.hash(), offset));
+        }
+    }
+
+    pub(crate) fn build(self, codec: CarIndexCodec) -> CarIndex {
+        match codec {
+            CarIndexCodec::Sorted => CarIndex::Sorted(IndexSorted::from_entries(
+                self.entries
+                    .into_iter()
+                    .map(|(mh, offset)| (mh.digest().to_vec(), offset)),
+            )),
+            CarIndexCodec::MultihashSorted => {
+                CarIndex::MultihashSorted(MultihashIndexSorted::from_entries(self.entries))
+            }
+        }
+    }
+}
+
+/// CARv2 index of any of the supported formats
+#[derive(Debug, Clone, PartialEq)]
+pub enum CarIndex {
+    Sorted(IndexSorted),
+    MultihashSorted(MultihashIndexSorted),
+}
+
+impl CarIndex {
+    /// Decodes an index, its format is picked from the multicodec prefix
+    pub fn decode(buf: &[u8]) -> Result<Self, CarDecodeError> {
+        match IndexReader(buf).read_varint()? {
+            CODE_INDEX_SORTED => Ok(CarIndex::Sorted(IndexSorted::decode(buf)?)),
+            CODE_MULTIHASH_INDEX_SORTED => Ok(CarIndex::MultihashSorted(
+                MultihashIndexSorted::decode(buf)?,
+            )),
+            codec => Err(CarDecodeError::UnsupportedIndexCodec {
+                codec,
+                location: None,
+            }),
+        }
+    }
+
+    /// Reads and decodes an index that runs to the end of `r`. An index longer than
+    /// `max_index_len` is rejected before it is fully read.
+    pub(crate) async fn read<R: AsyncRead + Unpin>(
+        r: &mut R,
+        max_index_len: u64,
+    ) -> Result<Self, CarDecodeError> {
+        let mut buf = vec![];
+        r.take(max_index_len.saturating_add(1))
+            .read_to_end(&mut buf)
+            .await?;
+        if buf.len() as u64 > max_index_len {
+            return Err(CarDecodeError::LimitExceeded {
+                limit: Limit::IndexLen,
+                value: buf.len() as u64,
+                max: max_index_len,
+                location: None,
+            });
+        }
+        Self::decode(&buf)
+    }
+
+    /// Encodes the index including its multicodec prefix
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            CarIndex::Sorted(index) => index.encode(),
+            CarIndex::MultihashSorted(index) => index.encode(),
+        }
+    }
+
+    /// Returns the offset of the section of a block with multihash `mh`, relative to
+    /// the start of the CARv1 data payload. An [`IndexSorted`] only matches the digest.
+    pub fn get(&self, mh: &Multihash<64>) -> Option<u64> {
+        match self {
+            CarIndex::Sorted(index) => index.get(mh.digest()),
+            CarIndex::MultihashSorted(index) => index.get(mh),
+        }
+    }
+
+    /// Count of entries in the index
+    pub fn len(&self) -> usize {
+        match self {
+            CarIndex::Sorted(index) => index.len(),
+            CarIndex::MultihashSorted(index) => index.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// CARv2 index in the IndexSorted format. Maps multihash digests to the byte offset
+/// of their section, relative to the start of the CARv1 data payload.
+///
+/// ```nn
+/// [codec varint][bucket count: i32][bucket]...[bucket]
+/// bucket = [width: u32][entries byte len: u64][digest|offset: u64]...[digest|offset: u64]
+/// ```
+///
+/// Buckets are sorted by width, entries within a bucket are sorted by digest.
+/// All integers are little-endian.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IndexSorted {
+    /// Entries (digest, offset) grouped by width = digest length + 8
+    buckets: BTreeMap<u32, Vec<(Vec<u8>, u64)>>,
+}
+
+impl IndexSorted {
+    /// Builds an index from (digest, section offset) pairs in any order
+    pub fn from_entries(entries: impl IntoIterator<Item = (Vec<u8>, u64)>) -> Self {
+        let mut buckets: BTreeMap<u32, Vec<(Vec<u8>, u64)>> = BTreeMap::new();
+        for (digest, offset) in entries {
+            let width = (digest.len() + 8) as u32;
+            buckets.entry(width).or_default().push((digest, offset));
+        }
+        for entries in buckets.values_mut() {
+            entries.sort();
+        }
+        IndexSorted { buckets }
+    }
+
+    /// Decodes an index including its multicodec prefix
+    pub fn decode(buf: &[u8]) -> Result<Self, CarDecodeError> {
+        let mut r = IndexReader(buf);
+        r.read_codec(CODE_INDEX_SORTED)?;
+        let index = Self::decode_buckets(&mut r)?;
+
+        if !r.0.is_empty() {
+            return Err(IndexError::TrailingBytes {
+                len: r.0.len() as u64,
+            }
+            .into());
+        }
+
+        Ok(index)
+    }
+
+    /// Decodes the index body after the multicodec prefix
+    fn decode_buckets(r: &mut IndexReader) -> Result<Self, CarDecodeError> {
+        let bucket_count = r.read_i32()?;
+        if bucket_count < 0 {
+            return Err(IndexError::NegativeBucketCount {
+                count: bucket_count,
+            }
+            .into());
+        }
+
+        let mut buckets = BTreeMap::new();
+        for _ in 0..bucket_count {
+            let width = r.read_u32()?;
+            let entries_len = r.read_u64()?;
+
+            if width < MIN_WIDTH {
+                return Err(IndexError::BucketWidth {
+                    width,
+                    min: MIN_WIDTH,
+                }
+                .into());
+            }
+            if entries_len % width as u64 != 0 {
+                return Err(IndexError::BucketEntriesLen {
+                    len: entries_len,
+                    width,
+                }
+                .into());
+            }
+
+            let digest_len = width as usize - 8;
+            let entries_buf = r.read_bytes(entries_len)?;
+            let entries = entries_buf
+                .chunks_exact(width as usize)
+                .map(|entry| {
+                    let (digest, offset) = entry.split_at(digest_len);
+                    // unwrap: chunk is exactly digest_len + 8 bytes
+                    (
+                        digest.to_vec(),
+                        u64::from_le_bytes(offset.try_into().unwrap()),
+                    )
+                })
+                .collect();
+
+            if buckets.insert(width, entries).is_some() {
+                return Err(IndexError::DuplicateBucketWidth { width }.into());
+            }
+        }
+
+        Ok(IndexSorted { buckets })
+    }
+
+    /// Encodes the index including its multicodec prefix
+    pub fn encode(&self) -> Vec<u8> {
+        let mut varint_buf = [0u8; U64_LEN];
+        let mut buf = encode_varint_u64(CODE_INDEX_SORTED, &mut varint_buf).to_vec();
+        self.encode_buckets(&mut buf);
+        buf
+    }
+
+    /// Encodes the index body after the multicodec prefix
+    fn encode_buckets(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.buckets.len() as i32).to_le_bytes());
+        for (width, entries) in &self.buckets {
+            buf.extend_from_slice(&width.to_le_bytes());
+            buf.extend_from_slice(&((*width as u64) * entries.len() as u64).to_le_bytes());
+            for (digest, offset) in entries {
+                buf.extend_from_slice(digest);
+                buf.extend_from_slice(&offset.to_le_bytes());
+            }
+        }
+    }
+
+    /// Returns the offset of the section of a block with multihash digest `digest`,
+    /// relative to the start of the CARv1 data payload.
+    pub fn get(&self, digest: &[u8]) -> Option<u64> {
+        let entries = self.buckets.get(&((digest.len() + 8) as u32))?;
+        let i = entries.partition_point(|(entry_digest, _)| entry_digest.as_slice() < digest);
+        match entries.get(i) {
+            Some((entry_digest, offset)) if entry_digest == digest => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Iterates all (digest, offset) entries, by width and then by digest
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], u64)> {
+        self.buckets
+            .values()
+            .flatten()
+            .map(|(digest, offset)| (digest.as_slice(), *offset))
+    }
+
+    /// Count of entries in the index
+    pub fn len(&self) -> usize {
+        self.buckets.values().map(|entries| entries.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// CARv2 index in the MultihashIndexSorted format. Same as [`IndexSorted`] but entries
+/// are first grouped by multihash code, so lookups match the full multihash.
+///
+/// ```nn
+/// [codec varint][code count: i32][code bucket]...[code bucket]
+/// code bucket = [multihash code: u64][IndexSorted without codec prefix]
+/// ```
+///
+/// Code buckets are sorted by multihash code. All integers are little-endian.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MultihashIndexSorted {
+    indexes: BTreeMap<u64, IndexSorted>,
+}
+
+impl MultihashIndexSorted {
+    /// Builds an index from (multihash, section offset) pairs in any order
+    pub fn from_entries(entries: impl IntoIterator<Item = (Multihash<64>, u64)>) -> Self {
+        let mut entries_by_code: BTreeMap<u64, Vec<(Vec<u8>, u64)>> = BTreeMap::new();
+        for (mh, offset) in entries {
+            entries_by_code
+                .entry(mh.code())
+                .or_default()
+                .push((mh.digest().to_vec(), offset));
+        }
+        MultihashIndexSorted {
+            indexes: entries_by_code
+                .into_iter()
+                .map(|(code, entries)| (code, IndexSorted::from_entries(entries)))
+                .collect(),
+        }
+    }
+
+    /// Decodes an index including its multicodec prefix
+    pub fn decode(buf: &[u8]) -> Result<Self, CarDecodeError> {
+        let mut r = IndexReader(buf);
+        r.read_codec(CODE_MULTIHASH_INDEX_SORTED)?;
+
+        let code_count = r.read_i32()?;
+        if code_count < 0 {
+            return Err(IndexError::NegativeCodeCount { count: code_count }.into());
+        }
+
+        let mut indexes = BTreeMap::new();
+        for _ in 0..code_count {
+            let code = r.read_u64()?;
+            let index = IndexSorted::decode_buckets(&mut r)?;
+            if indexes.insert(code, index).is_some() {
+                return Err(IndexError::DuplicateCode { code }.into());
+            }
+        }
+
+        if !r.0.is_empty() {
+            return Err(IndexError::TrailingBytes {
+                len: r.0.len() as u64,
+            }
+            .into());
+        }
+
+        Ok(MultihashIndexSorted { indexes })
+    }
+
+    /// Encodes the index including its multicodec prefix
+    pub fn encode(&self) -> Vec<u8> {
+        let mut varint_buf = [0u8; U64_LEN];
+        let mut buf = encode_varint_u64(CODE_MULTIHASH_INDEX_SORTED, &mut varint_buf).to_vec();
+
+        buf.extend_from_slice(&(self.indexes.len() as i32).to_le_bytes());
+        for (code, index) in &self.indexes {
+            buf.extend_from_slice(&code.to_le_bytes());
+            index.encode_buckets(&mut buf);
+        }
+        buf
+    }
+
+    /// Returns the offset of the section of a block with multihash `mh`,
+    /// relative to the start of the CARv1 data payload.
+    pub fn get(&self, mh: &Multihash<64>) -> Option<u64> {
+        self.indexes.get(&mh.code())?.get(mh.digest())
+    }
+
+    /// Iterates all (multihash code, digest, offset) entries, by code, width and then digest
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[u8], u64)> {
+        self.indexes.iter().flat_map(|(code, index)| {
+            index
+                .iter()
+                .map(move |(digest, offset)| (*code, digest, offset))
+        })
+    }
+
+    /// Count of entries in the index
+    pub fn len(&self) -> usize {
+        self.indexes.values().map(|index| index.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Reads little-endian integers and byte slices from an index buffer
+struct IndexReader<'a>(&'a [u8]);
+
+impl<'a> IndexReader<'a> {
+    fn read_varint(&mut self) -> Result<u64, CarDecodeError> {
+        let (value, varint_len) = decode_varint_u64(self.0).ok_or(IndexError::CodecVarint)?;
+        self.0 = &self.0[varint_len..];
+        Ok(value)
+    }
+
+    fn read_codec(&mut self, expected_codec: u64) -> Result<(), CarDecodeError> {
+        match self.read_varint()? {
+            codec if codec == expected_codec => Ok(()),
+            codec => Err(CarDecodeError::UnsupportedIndexCodec {
+                codec,
+                location: None,
+            }),
+        }
+    }
+
+    fn read_bytes(&mut self, len: u64) -> Result<&'a [u8], CarDecodeError> {
+        if len > self.0.len() as u64 {
+            return Err(IndexError::UnexpectedEnd {
+                expected: len,
+                left: self.0.len() as u64,
+            }
+            .into());
+        }
+        let (bytes, rest) = self.0.split_at(len as usize);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], CarDecodeError> {
+        // unwrap: read_bytes returns exactly N bytes
+        Ok(self.read_bytes(N as u64)?.try_into().unwrap())
+    }
+
+    fn read_i32(&mut self) -> Result<i32, CarDecodeError> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, CarDecodeError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, CarDecodeError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor;
+
+    use super::*;
+    use crate::{
+        car_block::decode_block, car_header::read_car_header, error::CarDecodeError,
+        CarReaderOptions,
+    };
+
+    /// Reads all sections of a CARv1 stream returning (cid, offset relative to data payload)
+    async fn section_offsets(car_filepath: &str) -> Vec<(Cid, u64)> {
+        let car = std::fs::read(car_filepath).unwrap();
+        let mut r = car.as_slice();
+        let options = CarReaderOptions::default();
+        read_car_header(&mut r, &options).await.unwrap();
+
+        let mut offset = (car.len() - r.len()) as u64;
+        let mut sections = vec![];
+        loop {
+            match decode_block(&mut r, &options).await {
+                Ok((cid, _, block_len, _)) => {
+                    sections.push((cid, offset));
+                    offset += block_len as u64;
+                }
+                Err(CarDecodeError::BlockStartEOF { .. }) => return sections,
+                Err(err) => panic!("{:?}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn decode_index_sorted_go_car_sample() {
+        executor::block_on(async {
+            let index_buf = std::fs::read("./tests/go_car_fixtures/sample-index.carindex").unwrap();
+            let index = IndexSorted::decode(&index_buf).unwrap();
+
+            let sections = section_offsets("./tests/go_car_fixtures/sample-v1.car").await;
+            assert_eq!(index.len(), sections.len());
+            for (cid, offset) in sections {
+                assert_eq!(index.get(cid.hash().digest()), Some(offset), "{}", cid);
+            }
+
+            assert_eq!(index.encode(), index_buf);
+        })
+    }
+
+    #[test]
+    fn decode_multihash_index_sorted_go_car_sample() {
+        executor::block_on(async {
+            let index_buf =
+                std::fs::read("./tests/go_car_fixtures/sample-multihash-index-sorted.carindex")
+                    .unwrap();
+            let index = MultihashIndexSorted::decode(&index_buf).unwrap();
+
+            // Index does not include identity CIDs
+            let sections = section_offsets("./tests/go_car_fixtures/sample-v1.car")
+                .await
+                .into_iter()
+                .filter(|(cid, _)| cid.hash().code() != CODE_IDENTITY)
+                .collect::<Vec<_>>();
+            assert_eq!(index.len(), sections.len());
+            for (cid, offset) in sections {
+                assert_eq!(index.get(cid.hash()), Some(offset), "{}", cid);
+            }
+
+            assert_eq!(index.encode(), index_buf);
+            assert_eq!(
+                CarIndex::decode(&index_buf).unwrap(),
+                CarIndex::MultihashSorted(index)
+            );
+        })
+    }
+
+    #[test]
+    fn multihash_index_sorted_mixed_codes() {
+        // Same digest with sha2-256 and blake2b-256 codes at different offsets
+        let digest = [7u8; 32];
+        let mh_sha2_256 = Multihash::<64>::wrap(0x12, &digest).unwrap();
+        let mh_blake2b_256 = Multihash::<64>::wrap(0xb220, &digest).unwrap();
+        let mh_sha2_512 = Multihash::<64>::wrap(0x13, &digest).unwrap();
+        let mh_other = Multihash::<64>::wrap(0x12, &[8u8; 32]).unwrap();
+
+        let index =
+            MultihashIndexSorted::from_entries(vec![(mh_blake2b_256, 200), (mh_sha2_256, 100)]);
+
+        assert_eq!(index.get(&mh_sha2_256), Some(100));
+        assert_eq!(index.get(&mh_blake2b_256), Some(200));
+        assert_eq!(index.get(&mh_sha2_512), None);
+        assert_eq!(index.get(&mh_other), None);
+        assert_eq!(
+            index.iter().collect::<Vec<_>>(),
+            vec![(0x12, &digest[..], 100), (0xb220, &digest[..], 200)]
+        );
+
+        let index_buf = index.encode();
+        assert_eq!(MultihashIndexSorted::decode(&index_buf).unwrap(), index);
+        let car_index = CarIndex::decode(&index_buf).unwrap();
+        assert_eq!(car_index.get(&mh_sha2_256), Some(100));
+        assert_eq!(car_index.get(&mh_blake2b_256), Some(200));
+    }
+
+    #[test]
+    fn index_sorted_get_missing() {
+        let index = IndexSorted::from_entries(vec![(vec![1; 32], 10), (vec![3; 32], 20)]);
+
+        assert_eq!(index.get(&[1; 32]), Some(10));
+        assert_eq!(index.get(&[3; 32]), Some(20));
+        assert_eq!(index.get(&[2; 32]), None);
+        assert_eq!(index.get(&[4; 32]), None);
+        assert_eq!(index.get(&[1; 20]), None);
+        assert_eq!(IndexSorted::decode(&index.encode()).unwrap(), index);
+    }
+
+    #[test]
+    fn decode_index_sorted_errors() {
+        for (index_hex, expected_err) in [
+            ("8108", "unsupported CARv2 index codec 0x401"),
+            ("80080100", "invalid CARv2 index: unexpected end of index, expected 4 bytes but only 2 left"),
+            ("8008ffffffff", "invalid CARv2 index: negative bucket count -1"),
+            ("800801000000040000000000000000000000", "invalid CARv2 index: bucket width 4 < min 8"),
+            ("800801000000090000001000000000000000", "invalid CARv2 index: bucket entries len 16 not multiple of width 9"),
+            ("80080100000008000000f8ffffffffffff7f", "invalid CARv2 index: unexpected end of index, expected 9223372036854775800 bytes but only 0 left"),
+            ("800800000000ff", "invalid CARv2 index: 1 trailing bytes"),
+        ] {
+            let err = IndexSorted::decode(&hex::decode(index_hex).unwrap()).unwrap_err();
+            assert_eq!(err.to_string(), expected_err, "{}", index_hex);
+        }
+    }
+
+    #[test]
+    fn decode_multihash_index_sorted_errors() {
+        for (index_hex, expected_err) in [
+            ("8008", "unsupported CARv2 index codec 0x400"),
+            (
+                "8108ffffffff",
+                "invalid CARv2 index: negative code count -1",
+            ),
+            (
+                "810802000000120000000000000000000000120000000000000000000000",
+                "invalid CARv2 index: duplicate multihash code 18",
+            ),
+            (
+                "810801000000120000000000000000000000ff",
+                "invalid CARv2 index: 1 trailing bytes",
+            ),
+        ] {
+            let err = MultihashIndexSorted::decode(&hex::decode(index_hex).unwrap()).unwrap_err();
+            assert_eq!(err.to_string(), expected_err, "{}", index_hex);
+        }
+
+        let err = CarIndex::decode(&hex::decode("8208").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported CARv2 index codec 0x402");
+    }
+}
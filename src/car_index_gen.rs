@@ -0,0 +1,161 @@
+use futures::AsyncRead;
+
+use crate::{
+    car_header::read_car_header,
+    car_index::{CarIndex, CarIndexBuilder, CarIndexCodec},
+    car_reader_options::CarReaderOptions,
+    car_section::SectionStream,
+    error::CarDecodeError,
+};
+
+/// Builds a CARv2 index of a CARv1 or CARv2 stream, reading all its sections once.
+/// Any existing index of a CARv2 is ignored. Blocks are not checked against their CID.
+/// Equivalent to go-car's `GenerateIndex`.
+///
+/// Offsets in the index are relative to the start of the CARv1 data payload, which for
+/// a CARv1 stream is its start.
+///
+/// # Examples
+///
+/// ```
+/// use rs_car::{generate_index, CarIndexCodec};
+///
+/// #[async_std::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///   let mut r = async_std::fs::File::open("./tests/go_car_fixtures/sample-v2-indexless.car").await?;
+///
+///   let index = generate_index(&mut r, CarIndexCodec::MultihashSorted).await?;
+///   let index_buf = index.encode();
+///   println!("index {} bytes", index_buf.len());
+///
+///   Ok(())
+/// }
+/// ```
+pub async fn generate_index<R: AsyncRead + Unpin>(
+    r: &mut R,
+    codec: CarIndexCodec,
+) -> Result<CarIndex, CarDecodeError> {
+    let options = CarReaderOptions::default().with_validate_block_hash(false);
+    generate_index_with_options(r, codec, options).await
+}
+
+/// Same as [`generate_index()`] with custom limits and checks. Blocks are checked against
+/// their CID if [`CarReaderOptions::validate_block_hash`] is set.
+pub async fn generate_index_with_options<R: AsyncRead + Unpin>(
+    r: &mut R,
+    codec: CarIndexCodec,
+    options: CarReaderOptions,
+) -> Result<CarIndex, CarDecodeError> {
+    let header = read_car_header(r, &options).await?;
+    let mut sections = SectionStream::new(&header, options);
+
+    let mut index = CarIndexBuilder::default();
+    while let Some(section) = sections.next_section(r).await {
+        let section = section?;
+        index.insert(&section.cid, section.offset - header.header_v1_offset());
+    }
+
+    Ok(index.build(codec))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor;
+
+    use super::*;
+    use crate::{car_read_all, Limit};
+
+    const SAMPLE_MULTIHASH_INDEX: &str =
+        "./tests/go_car_fixtures/sample-multihash-index-sorted.carindex";
+
+    async fn generate_index_of(car_filepath: &str, codec: CarIndexCodec) -> CarIndex {
+        let mut file = async_std::fs::File::open(car_filepath).await.unwrap();
+        generate_index(&mut file, codec).await.unwrap()
+    }
+
+    #[test]
+    fn generate_index_carv1_multihash_sorted() {
+        executor::block_on(async {
+            let index = generate_index_of(
+                "./tests/go_car_fixtures/sample-v1.car",
+                CarIndexCodec::MultihashSorted,
+            )
+            .await;
+            assert_eq!(
+                index.encode(),
+                std::fs::read(SAMPLE_MULTIHASH_INDEX).unwrap()
+            );
+        })
+    }
+
+    #[test]
+    fn generate_index_carv2_indexless() {
+        executor::block_on(async {
+            // Same data payload as sample-v1.car
+            let index = generate_index_of(
+                "./tests/go_car_fixtures/sample-v2-indexless.car",
+                CarIndexCodec::MultihashSorted,
+            )
+            .await;
+            assert_eq!(
+                index.encode(),
+                std::fs::read(SAMPLE_MULTIHASH_INDEX).unwrap()
+            );
+        })
+    }
+
+    #[test]
+    fn generate_index_carv2_sorted() {
+        executor::block_on(async {
+            // Same index as the existing one, without the multicodec prefix
+            let car_filepath = "./tests/spec_fixtures/carv2-basic.car";
+            let index = generate_index_of(car_filepath, CarIndexCodec::Sorted).await;
+            let car = std::fs::read(car_filepath).unwrap();
+            assert_eq!(hex::encode(&index.encode()[2..]), hex::encode(&car[499..]));
+
+            let (blocks, _) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+            assert_eq!(index.len(), blocks.len());
+        })
+    }
+
+    #[test]
+    fn generate_index_with_options_checks() {
+        executor::block_on(async {
+            // First section at byte 100, 91 bytes after its 1-byte varint
+            let mut car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+            let options = CarReaderOptions::default().with_max_block_len(90);
+            let err =
+                generate_index_with_options(&mut car.as_slice(), CarIndexCodec::Sorted, options)
+                    .await
+                    .unwrap_err();
+            assert!(
+                matches!(
+                    err,
+                    CarDecodeError::LimitExceeded {
+                        limit: Limit::BlockLen,
+                        ..
+                    }
+                ),
+                "{}",
+                err
+            );
+            assert_eq!(err.offset(), Some(100));
+
+            // Last byte of the first block, only checked if enabled
+            car[100 + 92 - 1] ^= 0xff;
+            generate_index(&mut car.as_slice(), CarIndexCodec::Sorted)
+                .await
+                .unwrap();
+            let options = CarReaderOptions::default();
+            let err =
+                generate_index_with_options(&mut car.as_slice(), CarIndexCodec::Sorted, options)
+                    .await
+                    .unwrap_err();
+            assert!(
+                matches!(err, CarDecodeError::BlockDigestMismatch { .. }),
+                "{}",
+                err
+            );
+        })
+    }
+}
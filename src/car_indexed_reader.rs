@@ -0,0 +1,480 @@
+use std::io::SeekFrom;
+
+use futures::{AsyncRead, AsyncSeek, AsyncSeekExt};
+
+use crate::{
+    block_cid::{assert_block_cid, CODE_IDENTITY},
+    car_block::{decode_block, decode_block_header},
+    car_header::read_car_header,
+    car_index::CarIndex,
+    car_reader_options::CarReaderOptions,
+    error::{CarDecodeError, IndexError},
+    CarHeader, Cid,
+};
+
+/// Random-access reader of an indexed CARv2 stream. Blocks are fetched by CID seeking
+/// straight to their section with the trailing CARv2 index, without scanning the data payload.
+///
+/// - To open an indexed CARv2 [`CarIndexedReader::new()`]
+pub struct CarIndexedReader<R> {
+    r: R,
+    pub header: CarHeader,
+    index: CarIndex,
+    /// Absolute position of the CARv1 data payload
+    data_start: u64,
+    options: CarReaderOptions,
+}
+
+impl<R> CarIndexedReader<R>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    /// Decodes a CARv2 header and its index. `r` must be positioned at the start of the
+    /// CARv2 stream. The CAR header is available in [`CarIndexedReader::header`].
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::{CarIndexedReader, Cid};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let r = async_std::fs::File::open("./tests/go_car_fixtures/sample-unixfs-v2.car").await?;
+    ///
+    ///   let mut car_reader = CarIndexedReader::new(r, true).await?;
+    ///   let root = car_reader.header.roots[0];
+    ///
+    ///   if let Some(block) = car_reader.get(&root).await? {
+    ///     println!("{:?} {} bytes", root, block.len());
+    ///   }
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub async fn new(
+        r: R,
+        validate_block_hash: bool,
+    ) -> Result<CarIndexedReader<R>, CarDecodeError> {
+        Self::with_options(
+            r,
+            CarReaderOptions {
+                validate_block_hash,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Same as [`CarIndexedReader::new()`] with custom limits and checks
+    pub async fn with_options(
+        mut r: R,
+        options: CarReaderOptions,
+    ) -> Result<CarIndexedReader<R>, CarDecodeError> {
+        let start = r.stream_position().await?;
+        let header = read_car_header(&mut r, &options).await?;
+
+        let header_v2 = match &header.header_v2 {
+            Some(header_v2) if header_v2.index_offset > 0 => header_v2,
+            _ => {
+                return Err(IndexError::NoIndex {
+                    version: header.version as u64,
+                }
+                .into())
+            }
+        };
+        let data_start = start.saturating_add(header_v2.data_offset);
+
+        let locate = |err: CarDecodeError| err.located(header_v2.index_offset, None);
+        r.seek(SeekFrom::Start(
+            start.saturating_add(header_v2.index_offset),
+        ))
+        .await
+        .map_err(|err| locate(err.into()))?;
+        let index = CarIndex::read(&mut r, options.max_index_len)
+            .await
+            .map_err(locate)?;
+
+        Ok(CarIndexedReader {
+            r,
+            header,
+            index,
+            data_start,
+            options,
+        })
+    }
+
+    /// Returns the block data of `cid`, or `None` if not in the index. An index offset outside
+    /// of the data payload, or to the section of another block, is an [`IndexError`].
+    ///
+    /// Blocks of identity CIDs are not indexed, their data is the CID digest. Any identity
+    /// CID returns `Some` without reading the stream, whether or not the CAR includes it.
+    pub async fn get(&mut self, cid: &Cid) -> Result<Option<Vec<u8>>, CarDecodeError> {
+        if cid.hash().code() == CODE_IDENTITY {
+            return Ok(Some(cid.hash().digest().to_vec()));
+        }
+
+        let Some(offset) = self.seek_to_section(cid).await? else {
+            return Ok(None);
+        };
+        let locate = |err: CarDecodeError| err.located(offset, None);
+
+        let (section_cid, block, _, _) = decode_block(&mut self.r, &self.options)
+            .await
+            .map_err(locate)?;
+        if !self.check_section_cid(&section_cid, cid).map_err(locate)? {
+            return Ok(None);
+        }
+
+        if self.options.validate_block_hash {
+            assert_block_cid(&section_cid, &block, &self.options.verifiers).map_err(locate)?;
+        }
+
+        Ok(Some(block))
+    }
+
+    /// Returns true if the index includes a block with the multihash of `cid`. Always true
+    /// for an identity CID, see [`CarIndexedReader::get()`].
+    pub async fn has(&mut self, cid: &Cid) -> Result<bool, CarDecodeError> {
+        Ok(self.get_size(cid).await?.is_some())
+    }
+
+    /// Returns the byte length of the block data of `cid`, or `None` if not in the index.
+    /// Only the section header is read. Always the digest length for an identity CID, see
+    /// [`CarIndexedReader::get()`].
+    pub async fn get_size(&mut self, cid: &Cid) -> Result<Option<usize>, CarDecodeError> {
+        if cid.hash().code() == CODE_IDENTITY {
+            return Ok(Some(cid.hash().digest().len()));
+        }
+
+        let Some(offset) = self.seek_to_section(cid).await? else {
+            return Ok(None);
+        };
+
+        let locate = |err: CarDecodeError| err.located(offset, None);
+
+        let (len, section_cid, _, cid_len) = decode_block_header(&mut self.r, &self.options)
+            .await
+            .map_err(locate)?;
+        if !self.check_section_cid(&section_cid, cid).map_err(locate)? {
+            return Ok(None);
+        }
+
+        Ok(Some(len - cid_len))
+    }
+
+    /// Index of the CARv2 stream
+    pub fn index(&self) -> &CarIndex {
+        &self.index
+    }
+
+    /// Returns the underlying reader
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    /// Seeks to the section of `cid`. Returns the absolute byte offset of the section from
+    /// the start of the CAR stream, or `None` if not in the index.
+    async fn seek_to_section(&mut self, cid: &Cid) -> Result<Option<u64>, CarDecodeError> {
+        match self.index.get(cid.hash()) {
+            Some(offset) => {
+                // Sections start after the CARv1 header and end with the data payload
+                let data_size = self.header.header_v2.as_ref().map_or(0, |h| h.data_size);
+                if offset < self.header.header_v1_len as u64 || offset >= data_size {
+                    return Err(IndexError::SectionOffsetOutOfRange { offset }.into());
+                }
+                let (Some(position), Some(section_offset)) = (
+                    self.data_start.checked_add(offset),
+                    self.header.header_v1_offset().checked_add(offset),
+                ) else {
+                    return Err(IndexError::SectionOffsetOutOfRange { offset }.into());
+                };
+                self.r.seek(SeekFrom::Start(position)).await?;
+                Ok(Some(section_offset))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Checks the CID of the section found with the index for `cid`. Returns false if the
+    /// section is a different block with the same digest, that an IndexSorted can't tell
+    /// apart, or an error if the index points to an unrelated section.
+    fn check_section_cid(&self, section_cid: &Cid, cid: &Cid) -> Result<bool, CarDecodeError> {
+        let digest_only = matches!(self.index, CarIndex::Sorted(_));
+        if section_cid.hash() == cid.hash() {
+            Ok(true)
+        } else if digest_only && section_cid.hash().digest() == cid.hash().digest() {
+            Ok(false)
+        } else {
+            Err(IndexError::SectionMismatch {
+                cid: Box::new(*section_cid),
+            }
+            .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use futures::{executor, io::Cursor};
+    use ipld_core::cid::multihash::Multihash;
+
+    use super::*;
+    use crate::{car_read_all, CarReader, CarSection, CarV2Writer, Limit};
+
+    async fn open_indexed(car_filepath: &str) -> CarIndexedReader<async_std::fs::File> {
+        let file = async_std::fs::File::open(car_filepath).await.unwrap();
+        CarIndexedReader::new(file, true).await.unwrap()
+    }
+
+    async fn assert_get_all_blocks(car_filepath: &str) {
+        let mut file = async_std::fs::File::open(car_filepath).await.unwrap();
+        let (blocks, _) = car_read_all(&mut file, true).await.unwrap();
+
+        let mut car_reader = open_indexed(car_filepath).await;
+        for (cid, block) in blocks {
+            assert_eq!(car_reader.get(&cid).await.unwrap(), Some(block.clone()));
+            assert_eq!(car_reader.get_size(&cid).await.unwrap(), Some(block.len()));
+            assert!(car_reader.has(&cid).await.unwrap());
+        }
+    }
+
+    #[test]
+    fn indexed_get_go_car_wrapped_v2() {
+        executor::block_on(assert_get_all_blocks(
+            "./tests/go_car_fixtures/sample-wrapped-v2.car",
+        ))
+    }
+
+    #[test]
+    fn indexed_get_go_car_rw_bs_v2() {
+        // Has padding between the CARv2 header and the data payload
+        executor::block_on(assert_get_all_blocks(
+            "./tests/go_car_fixtures/sample-rw-bs-v2.car",
+        ))
+    }
+
+    #[test]
+    fn indexed_get_go_car_unixfs_v2() {
+        executor::block_on(assert_get_all_blocks(
+            "./tests/go_car_fixtures/sample-unixfs-v2.car",
+        ))
+    }
+
+    #[test]
+    fn indexed_get_written_carv2() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+            let (blocks, header) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+
+            let mut car_writer = CarV2Writer::new(Cursor::new(Vec::new()), &header.roots)
+                .await
+                .unwrap();
+            for (cid, block) in &blocks {
+                car_writer.write(cid, block).await.unwrap();
+            }
+            let car_v2 = car_writer.finish().await.unwrap().into_inner();
+
+            let mut car_reader = CarIndexedReader::new(Cursor::new(car_v2), true)
+                .await
+                .unwrap();
+            assert_eq!(car_reader.header.roots, header.roots);
+            for (cid, block) in blocks {
+                assert_eq!(car_reader.get(&cid).await.unwrap(), Some(block));
+            }
+        })
+    }
+
+    #[test]
+    fn indexed_get_error_located() {
+        executor::block_on(async {
+            let mut car = std::fs::read("./tests/go_car_fixtures/sample-wrapped-v2.car").unwrap();
+            let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+            let section = car_reader.next_section().await.unwrap().unwrap();
+
+            // Last byte of the block
+            car[section.offset as usize + section.section_len() - 1] ^= 0xff;
+
+            let mut car_reader = CarIndexedReader::new(Cursor::new(car), true).await.unwrap();
+            let err = car_reader.get(&section.cid).await.unwrap_err();
+            assert!(
+                matches!(&err, CarDecodeError::BlockDigestMismatch { .. }),
+                "{}",
+                err
+            );
+            assert_eq!(err.offset(), Some(section.offset));
+            assert_eq!(err.section_index(), None);
+        })
+    }
+
+    /// Returns sample-wrapped-v2 with the index entry of its first section pointing to the
+    /// section at the absolute byte `offset`, and the sections of the CAR.
+    async fn wrapped_v2_with_index_offset(
+        offset: impl FnOnce(&[CarSection]) -> u64,
+    ) -> (Vec<u8>, Vec<CarSection>) {
+        let mut car = std::fs::read("./tests/go_car_fixtures/sample-wrapped-v2.car").unwrap();
+        let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+        let data_offset = car_reader.header.header_v1_offset();
+        let index_offset = car_reader.header.header_v2.as_ref().unwrap().index_offset as usize;
+        let mut sections = Vec::new();
+        while let Some(section) = car_reader.next_section().await {
+            let section = section.unwrap();
+            sections.push(section);
+        }
+
+        // Index entries are the digest followed by the u64 offset from the data payload
+        let digest = sections[0].cid.hash().digest();
+        let entry = index_offset
+            + car[index_offset..]
+                .windows(digest.len())
+                .position(|window| window == digest)
+                .unwrap()
+            + digest.len();
+        let offset = offset(&sections) - data_offset;
+        car[entry..entry + 8].copy_from_slice(&offset.to_le_bytes());
+        (car, sections)
+    }
+
+    #[test]
+    fn indexed_get_section_mismatch() {
+        executor::block_on(async {
+            let (car, sections) = wrapped_v2_with_index_offset(|sections| sections[1].offset).await;
+
+            let mut car_reader = CarIndexedReader::new(Cursor::new(car), true).await.unwrap();
+            for err in [
+                car_reader.get(&sections[0].cid).await.unwrap_err(),
+                car_reader.get_size(&sections[0].cid).await.unwrap_err(),
+            ] {
+                match &err {
+                    CarDecodeError::InvalidIndex {
+                        reason: IndexError::SectionMismatch { cid },
+                        ..
+                    } => assert_eq!(**cid, sections[1].cid),
+                    _ => panic!("expected SectionMismatch, got {}", err),
+                }
+                assert_eq!(err.offset(), Some(sections[1].offset));
+            }
+        })
+    }
+
+    #[test]
+    fn indexed_get_offset_out_of_range() {
+        executor::block_on(async {
+            // Inside the CARv1 header, and at the end of the data payload
+            let inside_header = |sections: &[CarSection]| sections[0].offset - 1;
+            let data_end = |sections: &[CarSection]| {
+                let last = sections.last().unwrap();
+                last.offset + last.section_len() as u64
+            };
+            for (car, sections) in [
+                wrapped_v2_with_index_offset(inside_header).await,
+                wrapped_v2_with_index_offset(data_end).await,
+            ] {
+                let mut car_reader = CarIndexedReader::new(Cursor::new(car), true).await.unwrap();
+                let err = car_reader.get(&sections[0].cid).await.unwrap_err();
+                assert!(
+                    matches!(
+                        err,
+                        CarDecodeError::InvalidIndex {
+                            reason: IndexError::SectionOffsetOutOfRange { .. },
+                            ..
+                        }
+                    ),
+                    "{}",
+                    err
+                );
+            }
+        })
+    }
+
+    #[test]
+    fn indexed_get_missing() {
+        executor::block_on(async {
+            let mut car_reader = open_indexed("./tests/go_car_fixtures/sample-unixfs-v2.car").await;
+            // Block of ./tests/spec_fixtures/carv1-basic.car
+            let cid = Cid::from_str("bafyreihyrpefhacm6kkp4ql6j6udakdit7g3dmkzfriqfykhjw6cad5lrm")
+                .unwrap();
+
+            assert_eq!(car_reader.get(&cid).await.unwrap(), None);
+            assert_eq!(car_reader.get_size(&cid).await.unwrap(), None);
+            assert!(!car_reader.has(&cid).await.unwrap());
+
+            // Identity CIDs are never looked up, even if not in the CAR
+            let identity = Cid::new_v1(0x55, Multihash::wrap(CODE_IDENTITY, b"abc").unwrap());
+            assert_eq!(
+                car_reader.get(&identity).await.unwrap(),
+                Some(b"abc".to_vec())
+            );
+            assert_eq!(car_reader.get_size(&identity).await.unwrap(), Some(3));
+            assert!(car_reader.has(&identity).await.unwrap());
+        })
+    }
+
+    #[test]
+    fn indexed_open_max_index_len() {
+        executor::block_on(async {
+            // Index of 150 bytes from byte 335 to the end
+            let car = std::fs::read("./tests/go_car_fixtures/sample-unixfs-v2.car").unwrap();
+            let options = CarReaderOptions {
+                max_index_len: 149,
+                ..Default::default()
+            };
+            match CarIndexedReader::with_options(Cursor::new(&car), options).await {
+                Err(CarDecodeError::LimitExceeded {
+                    limit: Limit::IndexLen,
+                    value: 150,
+                    max: 149,
+                    location: Some(location),
+                }) => assert_eq!(location.offset, 335),
+                Err(err) => panic!("other error {:?}", err),
+                Ok(_) => panic!("expected LimitExceeded"),
+            }
+        })
+    }
+
+    #[test]
+    fn indexed_open_index_error_located() {
+        executor::block_on(async {
+            // Index at byte 335
+            let car = std::fs::read("./tests/go_car_fixtures/sample-unixfs-v2.car").unwrap();
+            let err = CarIndexedReader::new(Cursor::new(&car[..car.len() - 1]), true)
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(err.location().map(|location| location.offset), Some(335));
+            assert!(
+                matches!(
+                    err,
+                    CarDecodeError::InvalidIndex {
+                        reason: IndexError::UnexpectedEnd { .. },
+                        ..
+                    }
+                ),
+                "{}",
+                err
+            );
+        })
+    }
+
+    #[test]
+    fn indexed_open_no_index() {
+        executor::block_on(async {
+            for (car_filepath, expected_err) in [
+                (
+                    "./tests/go_car_fixtures/sample-v2-indexless.car",
+                    "invalid CARv2 index: CARv2 has no index",
+                ),
+                (
+                    "./tests/go_car_fixtures/sample-v1.car",
+                    "invalid CARv2 index: CARv1 has no index",
+                ),
+            ] {
+                let file = async_std::fs::File::open(car_filepath).await.unwrap();
+                match CarIndexedReader::new(file, true).await {
+                    Err(err) => assert_eq!(err.to_string(), expected_err),
+                    Ok(_) => panic!("expected error for {}", car_filepath),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,353 @@
+use crate::multihash_verifier::MultihashVerifierRegistry;
+
+/// Limits and checks of a CAR stream decoded by [`crate::CarReader`], [`crate::CarDecoder`]
+/// or [`crate::CarIndexedReader`]. Limits reject malformed or hostile input before big
+/// allocations, tighten them for untrusted input or raise them for trusted archives.
+///
+/// More options may be added, start from [`CarReaderOptions::default()`] and set them
+/// with the `with_*` methods.
+///
+/// # Examples
+/// ```
+/// use rs_car::{CarReaderOptions, Strictness};
+///
+/// let options = CarReaderOptions::default()
+///   .with_max_block_len(1 << 20)
+///   .with_max_total_bytes(Some(1 << 30))
+///   .with_strictness(Strictness::Strict);
+/// ```
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct CarReaderOptions {
+    /// Max byte length of a CARv1 header after its varint, also of the CARv1 header
+    /// inside a CARv2
+    pub max_header_len: u64,
+    /// Max byte length of a section after its varint, CID and block data
+    pub max_block_len: u64,
+    /// Max byte length of the padding between the CARv2 header and the CARv1 data payload
+    pub max_padding_len: u64,
+    /// Max count of roots in the CAR header
+    pub max_roots: usize,
+    /// Max byte length of the CAR stream up to the end of the last section, `None` for
+    /// no limit
+    pub max_total_bytes: Option<u64>,
+    /// Max byte length of a CARv2 index read by [`crate::CarReader::read_index()`] or
+    /// [`crate::CarIndexedReader`]
+    pub max_index_len: u64,
+    /// Verify that each block digest matches its CID
+    pub validate_block_hash: bool,
+    /// Hash functions to verify blocks with, by multihash code. A block with any other
+    /// code is an `UnsupportedHashCode` error if `validate_block_hash` is set.
+    pub verifiers: MultihashVerifierRegistry,
+    /// Conformance to the CARv1 and CARv2 specs
+    pub strictness: Strictness,
+}
+
+/// Conformance to the CARv1 and CARv2 specs required by [`CarReaderOptions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// Accepts non-minimal varints, unknown header keys and characteristics, duplicate or
+    /// empty roots, and a stream that ends inside a section varint
+    #[default]
+    Lenient,
+    /// Rejects every deviation from the specs with a `SpecViolation` error
+    Strict,
+}
+
+impl Default for CarReaderOptions {
+    fn default() -> Self {
+        CarReaderOptions {
+            max_header_len: 1048576,
+            max_block_len: 1073741824,
+            max_padding_len: 1073741824,
+            max_roots: usize::MAX,
+            max_total_bytes: None,
+            max_index_len: 1073741824,
+            validate_block_hash: true,
+            verifiers: MultihashVerifierRegistry::default(),
+            strictness: Strictness::default(),
+        }
+    }
+}
+
+impl CarReaderOptions {
+    /// Sets [`CarReaderOptions::max_header_len`]
+    pub fn with_max_header_len(mut self, max_header_len: u64) -> Self {
+        self.max_header_len = max_header_len;
+        self
+    }
+
+    /// Sets [`CarReaderOptions::max_block_len`]
+    pub fn with_max_block_len(mut self, max_block_len: u64) -> Self {
+        self.max_block_len = max_block_len;
+        self
+    }
+
+    /// Sets [`CarReaderOptions::max_padding_len`]
+    pub fn with_max_padding_len(mut self, max_padding_len: u64) -> Self {
+        self.max_padding_len = max_padding_len;
+        self
+    }
+
+    /// Sets [`CarReaderOptions::max_roots`]
+    pub fn with_max_roots(mut self, max_roots: usize) -> Self {
+        self.max_roots = max_roots;
+        self
+    }
+
+    /// Sets [`CarReaderOptions::max_total_bytes`]
+    pub fn with_max_total_bytes(mut self, max_total_bytes: Option<u64>) -> Self {
+        self.max_total_bytes = max_total_bytes;
+        self
+    }
+
+    /// Sets [`CarReaderOptions::max_index_len`]
+    pub fn with_max_index_len(mut self, max_index_len: u64) -> Self {
+        self.max_index_len = max_index_len;
+        self
+    }
+
+    /// Sets [`CarReaderOptions::validate_block_hash`]
+    pub fn with_validate_block_hash(mut self, validate_block_hash: bool) -> Self {
+        self.validate_block_hash = validate_block_hash;
+        self
+    }
+
+    /// Sets [`CarReaderOptions::verifiers`]
+    pub fn with_verifiers(mut self, verifiers: MultihashVerifierRegistry) -> Self {
+        self.verifiers = verifiers;
+        self
+    }
+
+    /// Sets [`CarReaderOptions::strictness`]
+    pub fn with_strictness(mut self, strictness: Strictness) -> Self {
+        self.strictness = strictness;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor;
+
+    use super::*;
+    use crate::{
+        car_block::decode_block, car_read_all, CarDecodeError, CarReader, Limit, SpecViolation,
+    };
+
+    /// Reads all blocks of `car_filepath` with `options`, returning the first error
+    fn read_with_options(
+        car_filepath: &str,
+        options: CarReaderOptions,
+    ) -> Result<usize, CarDecodeError> {
+        read_bytes_with_options(&std::fs::read(car_filepath).unwrap(), options)
+    }
+
+    fn read_bytes_with_options(
+        car: &[u8],
+        options: CarReaderOptions,
+    ) -> Result<usize, CarDecodeError> {
+        executor::block_on(async {
+            let mut car_reader = CarReader::with_options(car, options).await?;
+            let mut count = 0;
+            while let Some(section) = car_reader.next_section().await {
+                section?;
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+
+    /// Asserts a `LimitExceeded` error at (offset, section index)
+    fn assert_limit_exceeded(
+        result: Result<usize, CarDecodeError>,
+        expected: (Limit, u64, u64),
+        location: (u64, Option<u64>),
+    ) {
+        let err = match result {
+            Err(err) => err,
+            Ok(count) => panic!("expected error but read {} blocks", count),
+        };
+        assert_eq!(
+            (err.offset(), err.section_index()),
+            (Some(location.0), location.1)
+        );
+        match err {
+            CarDecodeError::LimitExceeded {
+                limit, value, max, ..
+            } => {
+                assert_eq!((limit, value, max), expected)
+            }
+            err => panic!("expected LimitExceeded but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn default_options_same_as_new() {
+        let car_filepath = "./tests/go_car_fixtures/sample-rw-bs-v2.car";
+        let car = std::fs::read(car_filepath).unwrap();
+        let (blocks, _) = executor::block_on(car_read_all(&mut car.as_slice(), true)).unwrap();
+
+        let count = read_with_options(car_filepath, CarReaderOptions::default()).unwrap();
+        assert_eq!(count, blocks.len());
+    }
+
+    #[test]
+    fn max_header_len() {
+        // carv1-basic header is 99 bytes after its varint
+        let car_filepath = "./tests/spec_fixtures/carv1-basic.car";
+        let options = |max_header_len| CarReaderOptions {
+            max_header_len,
+            ..Default::default()
+        };
+
+        read_with_options(car_filepath, options(99)).unwrap();
+        assert_limit_exceeded(
+            read_with_options(car_filepath, options(98)),
+            (Limit::HeaderLen, 99, 98),
+            (0, None),
+        );
+    }
+
+    #[test]
+    fn max_block_len() {
+        // Largest section of carv1-basic is 131 bytes after its varint
+        let car_filepath = "./tests/spec_fixtures/carv1-basic.car";
+        let options = |max_block_len| CarReaderOptions {
+            max_block_len,
+            ..Default::default()
+        };
+
+        read_with_options(car_filepath, options(131)).unwrap();
+        assert_limit_exceeded(
+            read_with_options(car_filepath, options(130)),
+            (Limit::BlockLen, 131, 130),
+            (192, Some(1)),
+        );
+    }
+
+    #[test]
+    fn max_padding_len() {
+        // 1464 data offset - 51 bytes of pragma and header
+        let car_filepath = "./tests/go_car_fixtures/sample-rw-bs-v2.car";
+        let options = |max_padding_len| CarReaderOptions {
+            max_padding_len,
+            ..Default::default()
+        };
+
+        read_with_options(car_filepath, options(1413)).unwrap();
+        assert_limit_exceeded(
+            read_with_options(car_filepath, options(1412)),
+            (Limit::PaddingLen, 1413, 1412),
+            (51, None),
+        );
+    }
+
+    #[test]
+    fn max_roots() {
+        // carv1-basic has 2 roots
+        let car_filepath = "./tests/spec_fixtures/carv1-basic.car";
+        let options = |max_roots| CarReaderOptions {
+            max_roots,
+            ..Default::default()
+        };
+
+        read_with_options(car_filepath, options(2)).unwrap();
+        assert_limit_exceeded(
+            read_with_options(car_filepath, options(1)),
+            (Limit::Roots, 2, 1),
+            (0, None),
+        );
+    }
+
+    #[test]
+    fn max_total_bytes() {
+        let car_filepath = "./tests/spec_fixtures/carv1-basic.car";
+        let car_len = std::fs::metadata(car_filepath).unwrap().len();
+        let options = |max_total_bytes| CarReaderOptions {
+            max_total_bytes: Some(max_total_bytes),
+            ..Default::default()
+        };
+
+        read_with_options(car_filepath, options(car_len)).unwrap();
+        assert_limit_exceeded(
+            read_with_options(car_filepath, options(car_len - 1)),
+            (Limit::TotalBytes, car_len, car_len - 1),
+            (660, Some(7)),
+        );
+        // Header is 100 bytes including varint
+        assert_limit_exceeded(
+            read_with_options(car_filepath, options(99)),
+            (Limit::TotalBytes, 100, 99),
+            (0, None),
+        );
+    }
+
+    #[test]
+    fn strictness_non_minimal_section_varint() {
+        // First section of carv1-basic is at byte 100, with a 1 byte varint of 91
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let car = [&car[..100], &[0xdb, 0x00], &car[101..]].concat();
+        let options = |strictness| CarReaderOptions {
+            strictness,
+            ..Default::default()
+        };
+
+        read_bytes_with_options(&car, options(Strictness::Lenient)).unwrap();
+        let err = read_bytes_with_options(&car, options(Strictness::Strict)).unwrap_err();
+        assert_eq!((err.offset(), err.section_index()), (Some(100), Some(0)));
+        match err {
+            CarDecodeError::SpecViolation { violation, .. } => assert_eq!(
+                violation,
+                SpecViolation::NonMinimalVarint {
+                    len: 2,
+                    minimal_len: 1
+                }
+            ),
+            err => panic!("expected SpecViolation but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn strictness_truncated_section_varint() {
+        // 1 byte of a section varint after the last section of carv1-basic
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let car = [&car[..], &[0x80]].concat();
+        let options = |strictness| CarReaderOptions {
+            strictness,
+            ..Default::default()
+        };
+
+        read_bytes_with_options(&car, options(Strictness::Lenient)).unwrap();
+        let err = read_bytes_with_options(&car, options(Strictness::Strict)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "spec violation: stream ends inside a section varint after 1 bytes in section 8 at byte 715"
+        );
+
+        // Same from the section decoder without a poll state
+        let err = executor::block_on(decode_block(
+            &mut &car[car.len() - 1..],
+            &options(Strictness::Strict),
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            CarDecodeError::SpecViolation {
+                violation: SpecViolation::TruncatedSectionVarint { len: 1 },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn strictness_strict_reads_spec_fixtures() {
+        for car_filepath in [
+            "./tests/spec_fixtures/carv1-basic.car",
+            "./tests/spec_fixtures/carv2-basic.car",
+        ] {
+            let options = CarReaderOptions::default().with_strictness(Strictness::Strict);
+            read_with_options(car_filepath, options).unwrap();
+        }
+    }
+}
@@ -0,0 +1,787 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{future::poll_fn, ready, AsyncRead, AsyncReadExt};
+
+use crate::{
+    block_cid::{assert_block_cid, BlockHasher, MAX_CID_LEN},
+    car_block::{poll_read_some, PollDecodeBlock},
+    car_header::{CarHeader, StreamEnd},
+    car_index::CarIndex,
+    car_reader_options::CarReaderOptions,
+    carv2_header::CarV2Header,
+    error::{CarDecodeError, IndexError},
+    Cid,
+};
+
+/// Buffer size to skip the unread bytes of a streamed block
+const SKIP_BUF_LEN: usize = 8192;
+
+/// A block with the location of its section in the CAR stream
+///
+/// ```nn
+/// [varint|CID|block]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarSection {
+    pub cid: Cid,
+    pub block: Vec<u8>,
+    /// Absolute byte offset of the section varint, from the start of the CAR stream
+    pub offset: u64,
+    /// Byte length of the section varint
+    pub varint_len: usize,
+    /// Byte length of the CID
+    pub cid_len: usize,
+    /// Byte length of the block data
+    pub block_len: usize,
+}
+
+impl CarSection {
+    /// Total byte length of the section, including its varint
+    pub fn section_len(&self) -> usize {
+        self.varint_len + self.cid_len + self.block_len
+    }
+}
+
+/// A block streamed from its section in the CAR stream, read with `AsyncRead` instead of
+/// buffered in memory. Returned by [`crate::CarReader::next_block_reader()`].
+///
+/// If hash validation is enabled, the block is hashed as it is read and a digest mismatch
+/// is returned as an `InvalidData` error by the read that reaches the end of the block.
+pub struct CarBlockReader<'a, R> {
+    pub cid: Cid,
+    /// Absolute byte offset of the section varint, from the start of the CAR stream
+    pub offset: u64,
+    /// Byte length of the block data
+    pub block_len: usize,
+    sections: &'a mut SectionStream,
+    r: &'a mut R,
+}
+
+impl<R> AsyncRead for CarBlockReader<'_, R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.sections
+            .poll_read_block(cx, this.r, buf)
+            .map_err(|err| {
+                let kind = match &err {
+                    CarDecodeError::IoError { source, .. } => source.kind(),
+                    _ => io::ErrorKind::InvalidData,
+                };
+                io::Error::new(kind, err)
+            })
+    }
+}
+
+/// Decoding state of the sections after the CAR header, shared by the readers
+pub(crate) struct SectionStream {
+    eof_stream: StreamEnd,
+    header_v2: Option<CarV2Header>,
+    sections_offset: u64,
+    /// Byte length of all sections read so far
+    read_bytes: usize,
+    /// Ordinal of the next section
+    section_index: u64,
+    options: CarReaderOptions,
+    decode_block: PollDecodeBlock,
+    /// Block opened by [`SectionStream::next_block_reader`] and not fully verified yet
+    streamed_block: Option<StreamedBlock>,
+    finished: bool,
+    /// The sections ended cleanly and `r` is at the end of the data payload
+    at_data_end: bool,
+}
+
+/// (cid, section offset, block len)
+type OpenedBlock = (Cid, u64, usize);
+
+struct StreamedBlock {
+    /// Absolute byte offset and ordinal of the section, to locate errors
+    offset: u64,
+    section_index: u64,
+    /// Payload bytes read along with the CID, `prefix[prefix_pos..prefix_len]` are unread
+    prefix: [u8; MAX_CID_LEN],
+    prefix_pos: usize,
+    prefix_len: usize,
+    /// Payload bytes still in the reader
+    remaining: usize,
+    hasher: Option<BlockHasher>,
+}
+
+impl SectionStream {
+    pub(crate) fn new(header: &CarHeader, options: CarReaderOptions) -> Self {
+        SectionStream {
+            eof_stream: header.eof_stream,
+            header_v2: header.header_v2,
+            sections_offset: header.sections_offset(),
+            read_bytes: 0,
+            section_index: 0,
+            options,
+            decode_block: PollDecodeBlock::default(),
+            streamed_block: None,
+            finished: false,
+            at_data_end: false,
+        }
+    }
+
+    /// Decodes the next section from `r`. Returns `None` if the stream ended cleanly,
+    /// and after the first error.
+    pub(crate) fn poll_next_section<R: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        r: &mut R,
+    ) -> Poll<Option<Result<CarSection, CarDecodeError>>> {
+        if let Err(err) = ready!(self.poll_skip_streamed_block(cx, r)) {
+            return Poll::Ready(Some(Err(err)));
+        }
+
+        match self.is_finished() {
+            Ok(true) => return Poll::Ready(None),
+            Ok(false) => {}
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        }
+
+        let offset = self.sections_offset + self.read_bytes as u64;
+        let result = ready!(self.decode_block.poll_decode(cx, r, offset, &self.options));
+        let (cid, block, section_len, cid_len) = match self.end_on_error(result) {
+            Some(Ok(decoded)) => decoded,
+            Some(Err(err)) => return Poll::Ready(Some(Err(self.locate(err, offset)))),
+            None => return Poll::Ready(None),
+        };
+
+        if self.options.validate_block_hash {
+            if let Err(err) = assert_block_cid(&cid, &block, &self.options.verifiers) {
+                self.finished = true;
+                return Poll::Ready(Some(Err(self.locate(err, offset))));
+            }
+        }
+
+        let section = CarSection {
+            cid,
+            offset,
+            varint_len: section_len - cid_len - block.len(),
+            cid_len,
+            block_len: block.len(),
+            block,
+        };
+        self.read_bytes += section_len;
+        self.section_index += 1;
+
+        Poll::Ready(Some(Ok(section)))
+    }
+
+    /// Async version of [`SectionStream::poll_next_section`]
+    pub(crate) async fn next_section<R: AsyncRead + Unpin>(
+        &mut self,
+        r: &mut R,
+    ) -> Option<Result<CarSection, CarDecodeError>> {
+        poll_fn(|cx| self.poll_next_section(cx, r)).await
+    }
+
+    /// Decodes the next section up to its CID, the block is read from the returned reader.
+    /// Unread bytes of the previous block are skipped, and verified if enabled.
+    pub(crate) async fn next_block_reader<'a, R: AsyncRead + Unpin>(
+        &'a mut self,
+        r: &'a mut R,
+    ) -> Option<Result<CarBlockReader<'a, R>, CarDecodeError>> {
+        let (cid, offset, block_len) = match poll_fn(|cx| self.poll_next_block(cx, r)).await? {
+            Ok(block) => block,
+            Err(err) => return Some(Err(err)),
+        };
+
+        Some(Ok(CarBlockReader {
+            cid,
+            offset,
+            block_len,
+            sections: self,
+            r,
+        }))
+    }
+
+    fn poll_next_block<R: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        r: &mut R,
+    ) -> Poll<Option<Result<OpenedBlock, CarDecodeError>>> {
+        if let Err(err) = ready!(self.poll_skip_streamed_block(cx, r)) {
+            return Poll::Ready(Some(Err(err)));
+        }
+
+        match self.is_finished() {
+            Ok(true) => return Poll::Ready(None),
+            Ok(false) => {}
+            Err(err) => return Poll::Ready(Some(Err(err))),
+        }
+
+        let offset = self.sections_offset + self.read_bytes as u64;
+        let result = ready!(self
+            .decode_block
+            .poll_decode_start(cx, r, offset, &self.options));
+        let start = match self.end_on_error(result) {
+            Some(Ok(start)) => start,
+            Some(Err(err)) => return Poll::Ready(Some(Err(self.locate(err, offset)))),
+            None => return Poll::Ready(None),
+        };
+
+        let hasher = if self.options.validate_block_hash {
+            match BlockHasher::new(&start.cid, &self.options.verifiers) {
+                Ok(hasher) => Some(hasher),
+                Err(err) => {
+                    self.finished = true;
+                    return Poll::Ready(Some(Err(self.locate(err, offset))));
+                }
+            }
+        } else {
+            None
+        };
+
+        self.streamed_block = Some(StreamedBlock {
+            offset,
+            section_index: self.section_index,
+            prefix: start.prefix,
+            prefix_pos: 0,
+            prefix_len: start.prefix_len,
+            remaining: start.block_len - start.prefix_len,
+            hasher,
+        });
+
+        self.read_bytes += start.varint_len + start.cid_len + start.block_len;
+        self.section_index += 1;
+
+        Poll::Ready(Some(Ok((start.cid, offset, start.block_len))))
+    }
+
+    /// Skips the sections not read yet and the padding after the data payload, then reads
+    /// the CARv2 index up to the end of `r`. Returns `None` if the CAR has no index.
+    pub(crate) async fn read_index<R: AsyncRead + Unpin>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<Option<CarIndex>, CarDecodeError> {
+        let header_v2 = match self.header_v2 {
+            Some(header_v2) if header_v2.has_index() => header_v2,
+            _ => return Ok(None),
+        };
+
+        poll_fn(|cx| self.poll_skip_streamed_block(cx, r)).await?;
+        if !self.is_finished()? {
+            // Unread sections are skipped without decoding them
+            let offset = self.sections_offset + self.read_bytes as u64;
+            let data_end = header_v2.data_offset + header_v2.data_size;
+            self.finished = true;
+            skip_bytes(r, data_end - offset)
+                .await
+                .map_err(|err| self.locate(err.into(), offset))?;
+            self.at_data_end = true;
+        }
+        if !self.at_data_end {
+            return Err(IndexError::PositionUnknown.into());
+        }
+        // `r` is at the end of the stream after the index
+        self.at_data_end = false;
+
+        // Index is not before the data end after check_carv2_header
+        let data_end = header_v2.data_offset + header_v2.data_size;
+        skip_bytes(r, header_v2.index_offset - data_end)
+            .await
+            .map_err(|err| CarDecodeError::from(err).located(data_end, None))?;
+
+        CarIndex::read(r, self.options.max_index_len)
+            .await
+            .map(Some)
+            .map_err(|err| err.located(header_v2.index_offset, None))
+    }
+
+    /// Reads the streamed block into `buf`. Returns 0 once the block is fully read and
+    /// verified.
+    fn poll_read_block<R: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        r: &mut R,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, CarDecodeError>> {
+        let block = match &mut self.streamed_block {
+            Some(block) => block,
+            None => return Poll::Ready(Ok(0)),
+        };
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let n = if block.prefix_pos < block.prefix_len {
+            let prefix = &block.prefix[block.prefix_pos..block.prefix_len];
+            let n = prefix.len().min(buf.len());
+            buf[..n].copy_from_slice(&prefix[..n]);
+            block.prefix_pos += n;
+            n
+        } else if block.remaining > 0 {
+            let len = block.remaining.min(buf.len());
+            match ready!(poll_read_some(cx, r, &mut buf[..len])) {
+                Ok(n) => {
+                    block.remaining -= n;
+                    n
+                }
+                Err(err) => {
+                    let err =
+                        CarDecodeError::from(err).located(block.offset, Some(block.section_index));
+                    self.streamed_block = None;
+                    self.finished = true;
+                    return Poll::Ready(Err(err));
+                }
+            }
+        } else {
+            // End of the block, the digest is complete
+            let (offset, section_index) = (block.offset, block.section_index);
+            let hasher = self.streamed_block.take().and_then(|block| block.hasher);
+            if let Some(hasher) = hasher {
+                if let Err(err) = hasher.verify() {
+                    self.finished = true;
+                    return Poll::Ready(Err(err.located(offset, Some(section_index))));
+                }
+            }
+            return Poll::Ready(Ok(0));
+        };
+
+        if let Some(hasher) = &mut block.hasher {
+            hasher.update(&buf[..n]);
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    /// Reads the rest of the streamed block, if any, to position `r` at the next section
+    fn poll_skip_streamed_block<R: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        r: &mut R,
+    ) -> Poll<Result<(), CarDecodeError>> {
+        let mut buf = [0u8; SKIP_BUF_LEN];
+        while self.streamed_block.is_some() {
+            ready!(self.poll_read_block(cx, r, &mut buf))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Returns true if no more sections must be read. Ends the stream with an error if the
+    /// last section ended past the CARv2 data payload.
+    fn is_finished(&mut self) -> Result<bool, CarDecodeError> {
+        if self.finished {
+            return Ok(true);
+        }
+        let reached = self.eof_stream.is_reached(self.read_bytes);
+        self.finished = !matches!(reached, Ok(false));
+        self.at_data_end = matches!(reached, Ok(true));
+        reached
+    }
+
+    /// Locates an error of the next section at absolute byte `offset`
+    fn locate(&self, err: CarDecodeError, offset: u64) -> CarDecodeError {
+        err.located(offset, Some(self.section_index))
+    }
+
+    /// Ends the stream after an error. An EOF at the start of a CARv1 section is a clean end.
+    fn end_on_error<T>(
+        &mut self,
+        result: Result<T, CarDecodeError>,
+    ) -> Option<Result<T, CarDecodeError>> {
+        match result {
+            Ok(value) => Some(Ok(value)),
+            Err(CarDecodeError::BlockStartEOF { .. }) => {
+                self.finished = true;
+                self.eof_stream.block_start_eof().map(Err)
+            }
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Reads and discards `len` bytes of `r`, an EOF before is an error
+async fn skip_bytes<R: AsyncRead + Unpin>(r: &mut R, len: u64) -> io::Result<()> {
+    let skipped = futures::io::copy(r.take(len), &mut futures::io::sink()).await?;
+    if skipped < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor, AsyncReadExt};
+
+    use super::*;
+    use crate::{CarIndex, CarReader, Limit};
+
+    async fn read_sections(car: &[u8]) -> Vec<CarSection> {
+        let mut car_reader = CarReader::new(car, true).await.unwrap();
+        let mut sections = vec![];
+        while let Some(section) = car_reader.next_section().await {
+            sections.push(section.unwrap());
+        }
+        sections
+    }
+
+    #[test]
+    fn sections_carv1_basic() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+            let sections = read_sections(&car).await;
+
+            // Header is 1 byte varint + 99 bytes, block 0 section is 1 byte varint + 91 bytes
+            let section = &sections[0];
+            assert_eq!(
+                (
+                    section.offset,
+                    section.varint_len,
+                    section.cid_len,
+                    section.block_len
+                ),
+                (100, 1, 36, 55)
+            );
+            assert_eq!(section.block_len, section.block.len());
+
+            // Sections are contiguous up to the end of the stream
+            for pair in sections.windows(2) {
+                assert_eq!(
+                    pair[0].offset + pair[0].section_len() as u64,
+                    pair[1].offset
+                );
+            }
+            let last = sections.last().unwrap();
+            assert_eq!(last.offset + last.section_len() as u64, car.len() as u64);
+        })
+    }
+
+    #[test]
+    fn sections_carv2_offsets_match_index() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/go_car_fixtures/sample-wrapped-v2.car").unwrap();
+            let sections = read_sections(&car).await;
+
+            let car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+            let header_v2 = car_reader.header.header_v2.as_ref().unwrap();
+            let index = CarIndex::decode(&car[header_v2.index_offset as usize..]).unwrap();
+
+            // Index offsets are relative to the CARv1 data payload
+            for section in sections {
+                if let Some(offset) = index.get(section.cid.hash()) {
+                    assert_eq!(header_v2.data_offset + offset, section.offset);
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn sections_sync_same_as_async() {
+        let car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        let sections = executor::block_on(read_sections(&car));
+
+        let mut car_reader = crate::sync::CarReader::new(car.as_slice(), true).unwrap();
+        let mut sections_sync = vec![];
+        while let Some(section) = car_reader.next_section() {
+            sections_sync.push(section.unwrap());
+        }
+
+        assert_eq!(sections, sections_sync);
+        // 51 bytes pragma and header, 57 bytes inner CARv1 header
+        assert_eq!(sections[0].offset, 51 + 57);
+    }
+
+    #[test]
+    fn block_readers_same_as_sections() {
+        executor::block_on(async {
+            for car_filepath in [
+                "./tests/spec_fixtures/carv1-basic.car",
+                "./tests/spec_fixtures/carv2-basic.car",
+                "./tests/go_car_fixtures/sample-v1.car",
+                "./tests/go_car_fixtures/sample-wrapped-v2.car",
+            ] {
+                let car = std::fs::read(car_filepath).unwrap();
+                let sections = read_sections(&car).await;
+
+                let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+                let mut i = 0;
+                while let Some(block_reader) = car_reader.next_block_reader().await {
+                    let mut block_reader = block_reader.unwrap();
+                    let section = &sections[i];
+                    assert_eq!(block_reader.cid, section.cid, "{}", car_filepath);
+                    assert_eq!(block_reader.offset, section.offset, "{}", car_filepath);
+                    assert_eq!(
+                        block_reader.block_len, section.block_len,
+                        "{}",
+                        car_filepath
+                    );
+
+                    // Small reads, split between the CID prefix and the rest of the payload
+                    let mut block = vec![];
+                    let mut buf = [0u8; 5];
+                    loop {
+                        let n = block_reader.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            break;
+                        }
+                        block.extend_from_slice(&buf[..n]);
+                    }
+                    assert_eq!(block, section.block, "{}", car_filepath);
+                    i += 1;
+                }
+                assert_eq!(i, sections.len(), "{}", car_filepath);
+            }
+        })
+    }
+
+    #[test]
+    fn block_reader_partial_read_skips_to_next_section() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+            let sections = read_sections(&car).await;
+
+            let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+            let mut block_reader = car_reader.next_block_reader().await.unwrap().unwrap();
+            let mut buf = [0u8; 10];
+            block_reader.read_exact(&mut buf).await.unwrap();
+            assert_eq!(buf, sections[0].block[..10]);
+
+            // Rest of block 0 is skipped, next_section continues with block 1
+            let section = car_reader.next_section().await.unwrap().unwrap();
+            assert_eq!(section, sections[1]);
+
+            // Dropped block reader without reading
+            car_reader.next_block_reader().await.unwrap().unwrap();
+            let section = car_reader.next_section().await.unwrap().unwrap();
+            assert_eq!(section, sections[3]);
+        })
+    }
+
+    #[test]
+    fn block_reader_digest_mismatch_at_end() {
+        executor::block_on(async {
+            let mut car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+            // Last byte of block 0, header is 100 bytes and section 0 is 92 bytes
+            car[100 + 92 - 1] ^= 0xff;
+
+            let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+            let mut block_reader = car_reader.next_block_reader().await.unwrap().unwrap();
+
+            // All bytes are returned before the error
+            let mut block = vec![0u8; block_reader.block_len];
+            block_reader.read_exact(&mut block).await.unwrap();
+
+            let err = block_reader.read(&mut [0u8; 1]).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            let err = err.into_inner().unwrap();
+            let err = err.downcast_ref::<CarDecodeError>().unwrap();
+            assert!(
+                matches!(&err, CarDecodeError::BlockDigestMismatch { .. }),
+                "{}",
+                err
+            );
+            assert_eq!((err.offset(), err.section_index()), (Some(100), Some(0)));
+
+            // Stream ends after the error
+            assert!(car_reader.next_block_reader().await.is_none());
+        })
+    }
+
+    #[test]
+    fn block_reader_skipped_block_still_verified() {
+        executor::block_on(async {
+            let mut car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+            car[100 + 92 - 1] ^= 0xff;
+
+            let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+            car_reader.next_block_reader().await.unwrap().unwrap();
+
+            match car_reader.next_section().await {
+                Some(Err(err)) if matches!(&err, CarDecodeError::BlockDigestMismatch { .. }) => {
+                    assert_eq!((err.offset(), err.section_index()), (Some(100), Some(0)))
+                }
+                x => panic!("other result {:?}", x),
+            }
+            assert!(car_reader.next_section().await.is_none());
+        })
+    }
+
+    #[test]
+    fn block_readers_sync_same_as_async() {
+        let car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+        let sections = executor::block_on(read_sections(&car));
+
+        let mut car_reader = crate::sync::CarReader::new(car.as_slice(), true).unwrap();
+        let mut blocks = vec![];
+        while let Some(block_reader) = car_reader.next_block_reader() {
+            let mut block_reader = block_reader.unwrap();
+            let mut block = vec![];
+            std::io::Read::read_to_end(&mut block_reader, &mut block).unwrap();
+            blocks.push((block_reader.cid, block_reader.offset, block));
+        }
+
+        let expected = sections
+            .into_iter()
+            .map(|s| (s.cid, s.offset, s.block))
+            .collect::<Vec<_>>();
+        assert_eq!(blocks, expected);
+    }
+
+    /// Index of `car` read at its index offset
+    fn index_at_offset(car: &[u8]) -> CarIndex {
+        let index_offset = u64::from_le_bytes(car[43..51].try_into().unwrap());
+        CarIndex::decode(&car[index_offset as usize..]).unwrap()
+    }
+
+    #[test]
+    fn read_index_after_sections() {
+        executor::block_on(async {
+            for car_filepath in [
+                "./tests/go_car_fixtures/sample-unixfs-v2.car",
+                "./tests/go_car_fixtures/sample-wrapped-v2.car",
+                "./tests/go_car_fixtures/sample-rw-bs-v2.car",
+            ] {
+                let car = std::fs::read(car_filepath).unwrap();
+
+                let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+                while let Some(section) = car_reader.next_section().await {
+                    section.unwrap();
+                }
+                let index = car_reader.read_index().await.unwrap();
+                assert_eq!(index, Some(index_at_offset(&car)), "{}", car_filepath);
+
+                // Unread sections and a partially read block are skipped
+                let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+                let mut block_reader = car_reader.next_block_reader().await.unwrap().unwrap();
+                block_reader.read_exact(&mut [0u8; 1]).await.unwrap();
+                let index = car_reader.read_index().await.unwrap();
+                assert_eq!(index, Some(index_at_offset(&car)), "{}", car_filepath);
+
+                let mut car_reader = crate::sync::CarReader::new(car.as_slice(), true).unwrap();
+                let index = car_reader.read_index().unwrap();
+                assert_eq!(index, Some(index_at_offset(&car)), "{}", car_filepath);
+            }
+        })
+    }
+
+    #[test]
+    fn read_index_after_padding() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+            let options = crate::CarV2WriterOptions {
+                data_padding: 5,
+                index_padding: 7,
+                ..Default::default()
+            };
+            let mut car_v2 = futures::io::Cursor::new(vec![]);
+            crate::wrap_v1(&mut car.as_slice(), &mut car_v2, options)
+                .await
+                .unwrap();
+            let car_v2 = car_v2.into_inner();
+
+            let mut car_reader = CarReader::new(car_v2.as_slice(), true).await.unwrap();
+            let index = car_reader.read_index().await.unwrap();
+            assert_eq!(index, Some(index_at_offset(&car_v2)));
+        })
+    }
+
+    #[test]
+    fn read_index_none() {
+        executor::block_on(async {
+            for car_filepath in [
+                "./tests/spec_fixtures/carv1-basic.car",
+                "./tests/go_car_fixtures/sample-v2-indexless.car",
+            ] {
+                let car = std::fs::read(car_filepath).unwrap();
+                let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+                assert_eq!(
+                    car_reader.read_index().await.unwrap(),
+                    None,
+                    "{}",
+                    car_filepath
+                );
+                // Sections are still readable
+                assert!(
+                    car_reader.next_section().await.is_some(),
+                    "{}",
+                    car_filepath
+                );
+            }
+        })
+    }
+
+    #[test]
+    fn read_index_max_index_len() {
+        executor::block_on(async {
+            // Index of 150 bytes from byte 335 to the end
+            let car = std::fs::read("./tests/go_car_fixtures/sample-unixfs-v2.car").unwrap();
+            let car = car.as_slice();
+            let read_index = |max_index_len| async move {
+                let options = CarReaderOptions {
+                    max_index_len,
+                    ..Default::default()
+                };
+                let mut car_reader = CarReader::with_options(car, options).await.unwrap();
+                car_reader.read_index().await
+            };
+
+            read_index(150).await.unwrap().unwrap();
+            match read_index(149).await {
+                Err(CarDecodeError::LimitExceeded {
+                    limit: Limit::IndexLen,
+                    value: 150,
+                    max: 149,
+                    location: Some(location),
+                }) => assert_eq!(location.offset, 335),
+                x => panic!("other result {:?}", x),
+            }
+        })
+    }
+
+    #[test]
+    fn read_index_errors() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/go_car_fixtures/sample-unixfs-v2.car").unwrap();
+
+            // Index read twice
+            let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+            car_reader.read_index().await.unwrap();
+            let err = car_reader.read_index().await.unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "invalid CARv2 index: index position unknown after a section error or a previous read"
+            );
+
+            // Truncated in the sections, data payload ends at byte 335
+            let mut car_reader = CarReader::new(&car[..300], true).await.unwrap();
+            let err = car_reader.read_index().await.unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "io error: unexpected end of file in section 0 at byte 110"
+            );
+
+            // Truncated in the index
+            let mut car_reader = CarReader::new(&car[..car.len() - 1], true).await.unwrap();
+            let err = car_reader.read_index().await.unwrap_err();
+            assert_eq!(err.offset(), Some(335));
+            assert!(
+                matches!(
+                    &err,
+                    CarDecodeError::InvalidIndex {
+                        reason: IndexError::UnexpectedEnd { .. },
+                        ..
+                    }
+                ),
+                "{}",
+                err
+            );
+        })
+    }
+}
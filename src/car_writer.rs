@@ -0,0 +1,582 @@
+use std::{collections::BTreeMap, io::SeekFrom};
+
+use futures::{io, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use ipld_core::ipld::Ipld;
+
+use crate::{
+    car_block::encode_block_header,
+    car_index::{CarIndexBuilder, CarIndexCodec},
+    carv1_header::{encode_carv1_header, CarV1Header},
+    carv2_header::{
+        encode_carv2_header, CarV2Header, Characteristics, CARV2_HEADER_SIZE, CARV2_PRAGMA,
+        CARV2_PRAGMA_SIZE,
+    },
+    error::CarEncodeError,
+    varint::{encode_varint_u64, U64_LEN},
+    Cid,
+};
+
+/// Encodes a CARv1 stream writing its header and then blocks one by one.
+/// Output can be decoded back with [`crate::CarReader`].
+///
+/// - To get a block writer [`CarWriter::new()`]
+pub struct CarWriter<W> {
+    w: W,
+    written_bytes: usize,
+}
+
+impl<W> CarWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// Writes the CARv1 header with `roots` to `w`. Returns a writer to append
+    /// blocks with [`CarWriter::write()`].
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::{car_read_all, CarWriter, Cid};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let cid = Cid::try_from("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf")?;
+    ///   let block = hex::decode("0a110802120b68656c6c6f776f726c640a180b")?;
+    ///
+    ///   let mut car_writer = CarWriter::new(Vec::new(), &[cid]).await?;
+    ///   car_writer.write(&cid, &block).await?;
+    ///   let car = car_writer.finish().await?;
+    ///
+    ///   let (blocks, header) = car_read_all(&mut car.as_slice(), true).await?;
+    ///   assert_eq!(header.roots, vec![cid]);
+    ///   assert_eq!(blocks, vec![(cid, block)]);
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub async fn new(w: W, roots: &[Cid]) -> Result<CarWriter<W>, CarEncodeError> {
+        Self::with_extra(w, roots, &BTreeMap::new()).await
+    }
+
+    /// Same as [`CarWriter::new()`] with `extra` keys in the CARv1 header, as read back in
+    /// [`crate::CarHeader::extra`]. `extra` can not have the `roots` or `version` keys.
+    pub async fn with_extra(
+        mut w: W,
+        roots: &[Cid],
+        extra: &BTreeMap<String, Ipld>,
+    ) -> Result<CarWriter<W>, CarEncodeError> {
+        let header = CarV1Header {
+            version: 1,
+            roots: Some(roots.to_vec()),
+            extra: extra.clone(),
+        };
+        let written_bytes = write_carv1_header(&mut w, &header).await?;
+
+        Ok(CarWriter { w, written_bytes })
+    }
+
+    /// Appends a block section `[varint|CID|block]`. The block is not checked
+    /// against the CID's multihash.
+    pub async fn write(&mut self, cid: &Cid, block: &[u8]) -> Result<(), CarEncodeError> {
+        let block_header = encode_block_header(cid, block.len());
+        self.w.write_all(&block_header).await?;
+        self.w.write_all(block).await?;
+        self.written_bytes += block_header.len() + block.len();
+        Ok(())
+    }
+
+    /// Total byte length written so far, including the header
+    pub fn written_bytes(&self) -> usize {
+        self.written_bytes
+    }
+
+    /// Flushes the underlying writer and returns it
+    pub async fn finish(mut self) -> Result<W, CarEncodeError> {
+        self.w.flush().await?;
+        Ok(self.w)
+    }
+}
+
+/// Layout options of a CARv2 stream written by [`CarV2Writer`] or [`CarV2BufferedWriter`]
+#[derive(Debug, Clone, Default)]
+pub struct CarV2WriterOptions {
+    /// Count of zero bytes between the CARv2 header and the CARv1 data payload
+    pub data_padding: u64,
+    /// Count of zero bytes between the CARv1 data payload and the index
+    pub index_padding: u64,
+    /// Do not write an index, the header `index_offset` is set to 0
+    pub omit_index: bool,
+    /// Format of the index
+    pub index_codec: CarIndexCodec,
+}
+
+/// Encodes a CARv2 stream to a seekable output. The CARv2 header is written with
+/// zeroed locations first, then filled in on [`CarV2Writer::finish()`].
+///
+/// For non-seekable outputs use [`CarV2BufferedWriter`].
+///
+/// ```nn
+/// [pragma][v2 header][opt padding][CARv1][opt padding][opt index]
+/// ```
+pub struct CarV2Writer<W> {
+    inner: CarWriter<W>,
+    options: CarV2WriterOptions,
+    /// Position of the output when the writer was created
+    start: u64,
+    index: CarIndexBuilder,
+}
+
+impl<W> CarV2Writer<W>
+where
+    W: AsyncWrite + AsyncSeek + Unpin,
+{
+    /// Writes the CARv2 pragma, a placeholder header and the inner CARv1 header
+    /// with `roots`. Returns a writer to append blocks with [`CarV2Writer::write()`].
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::{car_read_all, CarV2Writer, Cid};
+    /// use futures::io::Cursor;
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let cid = Cid::try_from("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf")?;
+    ///   let block = hex::decode("0a110802120b68656c6c6f776f726c640a180b")?;
+    ///
+    ///   let mut car_writer = CarV2Writer::new(Cursor::new(Vec::new()), &[cid]).await?;
+    ///   car_writer.write(&cid, &block).await?;
+    ///   let car = car_writer.finish().await?.into_inner();
+    ///
+    ///   let (blocks, header) = car_read_all(&mut car.as_slice(), true).await?;
+    ///   assert_eq!(header.roots, vec![cid]);
+    ///   assert_eq!(blocks, vec![(cid, block)]);
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub async fn new(w: W, roots: &[Cid]) -> Result<CarV2Writer<W>, CarEncodeError> {
+        Self::with_options(w, roots, CarV2WriterOptions::default()).await
+    }
+
+    /// Same as [`CarV2Writer::new()`] with custom layout options
+    pub async fn with_options(
+        w: W,
+        roots: &[Cid],
+        options: CarV2WriterOptions,
+    ) -> Result<CarV2Writer<W>, CarEncodeError> {
+        Self::with_extra(w, roots, &BTreeMap::new(), options).await
+    }
+
+    /// Same as [`CarV2Writer::with_options()`] with `extra` keys in the inner CARv1
+    /// header, see [`CarWriter::with_extra()`]
+    pub async fn with_extra(
+        mut w: W,
+        roots: &[Cid],
+        extra: &BTreeMap<String, Ipld>,
+        options: CarV2WriterOptions,
+    ) -> Result<CarV2Writer<W>, CarEncodeError> {
+        let start = write_carv2_placeholder_header(&mut w, &options).await?;
+
+        Ok(CarV2Writer {
+            inner: CarWriter::with_extra(w, roots, extra).await?,
+            options,
+            start,
+            index: CarIndexBuilder::default(),
+        })
+    }
+
+    /// Appends a block section `[varint|CID|block]` to the CARv1 data payload.
+    /// The block is not checked against the CID's multihash.
+    pub async fn write(&mut self, cid: &Cid, block: &[u8]) -> Result<(), CarEncodeError> {
+        self.index.insert(cid, self.inner.written_bytes() as u64);
+        self.inner.write(cid, block).await
+    }
+
+    /// Writes the index, fills in the CARv2 header, and returns the output
+    /// positioned at the end of the CARv2 stream.
+    pub async fn finish(self) -> Result<W, CarEncodeError> {
+        let data_size = self.inner.written_bytes();
+        let mut w = self.inner.finish().await?;
+
+        write_carv2_index_and_header(&mut w, self.start, &self.options, self.index, data_size)
+            .await?;
+
+        Ok(w)
+    }
+}
+
+/// Encodes a CARv2 stream to a non-seekable output. The CARv1 data payload is
+/// buffered in memory until [`CarV2BufferedWriter::finish()`], when its size is
+/// known and the whole CARv2 stream is written at once.
+///
+/// For seekable outputs prefer [`CarV2Writer`], which does not buffer.
+pub struct CarV2BufferedWriter<W> {
+    w: W,
+    inner: CarWriter<Vec<u8>>,
+    options: CarV2WriterOptions,
+    index: CarIndexBuilder,
+}
+
+impl<W> CarV2BufferedWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// Returns a writer to append blocks with [`CarV2BufferedWriter::write()`].
+    /// Nothing is written to `w` until [`CarV2BufferedWriter::finish()`].
+    pub async fn new(w: W, roots: &[Cid]) -> Result<CarV2BufferedWriter<W>, CarEncodeError> {
+        Self::with_options(w, roots, CarV2WriterOptions::default()).await
+    }
+
+    /// Same as [`CarV2BufferedWriter::new()`] with custom layout options
+    pub async fn with_options(
+        w: W,
+        roots: &[Cid],
+        options: CarV2WriterOptions,
+    ) -> Result<CarV2BufferedWriter<W>, CarEncodeError> {
+        Self::with_extra(w, roots, &BTreeMap::new(), options).await
+    }
+
+    /// Same as [`CarV2BufferedWriter::with_options()`] with `extra` keys in the inner
+    /// CARv1 header, see [`CarWriter::with_extra()`]
+    pub async fn with_extra(
+        w: W,
+        roots: &[Cid],
+        extra: &BTreeMap<String, Ipld>,
+        options: CarV2WriterOptions,
+    ) -> Result<CarV2BufferedWriter<W>, CarEncodeError> {
+        Ok(CarV2BufferedWriter {
+            w,
+            inner: CarWriter::with_extra(Vec::new(), roots, extra).await?,
+            options,
+            index: CarIndexBuilder::default(),
+        })
+    }
+
+    /// Appends a block section `[varint|CID|block]` to the buffered CARv1 data payload.
+    /// The block is not checked against the CID's multihash.
+    pub async fn write(&mut self, cid: &Cid, block: &[u8]) -> Result<(), CarEncodeError> {
+        self.index.insert(cid, self.inner.written_bytes() as u64);
+        self.inner.write(cid, block).await
+    }
+
+    /// Writes the full CARv2 stream and returns the output
+    pub async fn finish(self) -> Result<W, CarEncodeError> {
+        let mut w = self.w;
+        let header = carv2_header_for(&self.options, self.inner.written_bytes());
+        let data = self.inner.finish().await?;
+
+        w.write_all(&CARV2_PRAGMA).await?;
+        w.write_all(&encode_carv2_header(&header)).await?;
+        write_padding(&mut w, self.options.data_padding).await?;
+        w.write_all(&data).await?;
+        write_index(&mut w, &self.options, self.index).await?;
+        w.flush().await?;
+
+        Ok(w)
+    }
+}
+
+/// Writes the CARv2 pragma, a zeroed CARv2 header and the data padding.
+/// Returns the position of the output before writing, the start of the CARv2 stream.
+pub(crate) async fn write_carv2_placeholder_header<W: AsyncWrite + AsyncSeek + Unpin>(
+    w: &mut W,
+    options: &CarV2WriterOptions,
+) -> io::Result<u64> {
+    let start = w.stream_position().await?;
+
+    w.write_all(&CARV2_PRAGMA).await?;
+    w.write_all(&[0u8; CARV2_HEADER_SIZE]).await?;
+    write_padding(w, options.data_padding).await?;
+
+    Ok(start)
+}
+
+/// Writes the index after a CARv1 data payload of `data_size` bytes, then goes back to
+/// fill in the CARv2 header of the stream at `start`. Leaves the output at the end.
+pub(crate) async fn write_carv2_index_and_header<W: AsyncWrite + AsyncSeek + Unpin>(
+    w: &mut W,
+    start: u64,
+    options: &CarV2WriterOptions,
+    index: CarIndexBuilder,
+    data_size: usize,
+) -> io::Result<()> {
+    let header = carv2_header_for(options, data_size);
+
+    write_index(w, options, index).await?;
+    let end = w.stream_position().await?;
+
+    w.seek(SeekFrom::Start(start + CARV2_PRAGMA_SIZE as u64))
+        .await?;
+    w.write_all(&encode_carv2_header(&header)).await?;
+    w.seek(SeekFrom::Start(end)).await?;
+    w.flush().await
+}
+
+fn carv2_header_for(options: &CarV2WriterOptions, data_size: usize) -> CarV2Header {
+    let data_offset = (CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE) as u64 + options.data_padding;
+    let data_size = data_size as u64;
+    let index_offset = if options.omit_index {
+        0
+    } else {
+        data_offset + data_size + options.index_padding
+    };
+
+    CarV2Header {
+        characteristics: Characteristics::empty(),
+        data_offset,
+        data_size,
+        index_offset,
+    }
+}
+
+async fn write_index<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    options: &CarV2WriterOptions,
+    index: CarIndexBuilder,
+) -> io::Result<()> {
+    if !options.omit_index {
+        write_padding(w, options.index_padding).await?;
+        w.write_all(&index.build(options.index_codec).encode())
+            .await?;
+    }
+    Ok(())
+}
+
+async fn write_padding<W: AsyncWrite + Unpin>(w: &mut W, len: u64) -> io::Result<()> {
+    io::copy(io::repeat(0).take(len), w).await?;
+    Ok(())
+}
+
+/// # Returns
+///
+/// total header byte length including varint
+pub(crate) async fn write_carv1_header<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    header: &CarV1Header,
+) -> Result<usize, CarEncodeError> {
+    let header_buf = encode_carv1_header(header)?;
+
+    let mut varint_buf = [0u8; U64_LEN];
+    let varint = encode_varint_u64(header_buf.len() as u64, &mut varint_buf);
+
+    w.write_all(varint).await?;
+    w.write_all(&header_buf).await?;
+
+    Ok(varint.len() + header_buf.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor, io::Cursor};
+
+    use super::*;
+    use crate::{car_read_all, carv2_header::decode_carv2_header};
+
+    async fn assert_roundtrip(car_filepath: &str) {
+        let car = std::fs::read(car_filepath).unwrap();
+        let (blocks, header) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+
+        let mut car_writer = CarWriter::with_extra(Vec::new(), &header.roots, &header.extra)
+            .await
+            .unwrap();
+        for (cid, block) in &blocks {
+            car_writer.write(cid, block).await.unwrap();
+        }
+        assert_eq!(car_writer.written_bytes(), car.len());
+        let car_out = car_writer.finish().await.unwrap();
+
+        assert_eq!(hex::encode(&car_out), hex::encode(&car));
+    }
+
+    #[test]
+    fn write_carv1_helloworld() {
+        executor::block_on(assert_roundtrip("./tests/custom_fixtures/helloworld.car"))
+    }
+
+    #[test]
+    fn write_carv1_basic() {
+        executor::block_on(assert_roundtrip("./tests/spec_fixtures/carv1-basic.car"))
+    }
+
+    #[test]
+    fn write_carv1_go_car_sample() {
+        executor::block_on(assert_roundtrip("./tests/go_car_fixtures/sample-v1.car"))
+    }
+
+    #[test]
+    fn write_carv1_no_blocks() {
+        executor::block_on(async {
+            let car_writer = CarWriter::new(Vec::new(), &[]).await.unwrap();
+            let car = car_writer.finish().await.unwrap();
+
+            let (blocks, header) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+            assert_eq!(blocks, vec![]);
+            assert_eq!(header.roots, vec![]);
+        })
+    }
+
+    #[test]
+    fn write_carv1_extra_roundtrip() {
+        executor::block_on(async {
+            let cid = Cid::try_from("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf").unwrap();
+            let extra = BTreeMap::from([
+                ("dups".to_string(), Ipld::Bool(true)),
+                ("producer".to_string(), Ipld::String("rs-car".to_string())),
+            ]);
+
+            let car_writer = CarWriter::with_extra(Vec::new(), &[cid], &extra)
+                .await
+                .unwrap();
+            let car = car_writer.finish().await.unwrap();
+            let (_, header) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+            assert_eq!(header.extra, extra);
+
+            let car_writer = CarV2Writer::with_extra(
+                Cursor::new(Vec::new()),
+                &[cid],
+                &extra,
+                Default::default(),
+            )
+            .await
+            .unwrap();
+            let car = car_writer.finish().await.unwrap().into_inner();
+            let (_, header) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+            assert_eq!(header.extra, extra);
+        })
+    }
+
+    #[test]
+    fn write_carv1_extra_reserved_key() {
+        executor::block_on(async {
+            let extra = BTreeMap::from([("roots".to_string(), Ipld::List(vec![]))]);
+            match CarWriter::with_extra(Vec::new(), &[], &extra).await {
+                Err(err) => assert_eq!(
+                    err.to_string(),
+                    "invalid CARv1 header: extra key roots is reserved"
+                ),
+                Ok(_) => panic!("expected error"),
+            }
+        })
+    }
+
+    async fn write_carv2(
+        car_filepath: &str,
+        options: CarV2WriterOptions,
+    ) -> (Vec<(Cid, Vec<u8>)>, Vec<u8>) {
+        let car = std::fs::read(car_filepath).unwrap();
+        let (blocks, header) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+
+        let mut car_writer = CarV2Writer::with_extra(
+            Cursor::new(Vec::new()),
+            &header.roots,
+            &header.extra,
+            options.clone(),
+        )
+        .await
+        .unwrap();
+        let mut car_buffered_writer =
+            CarV2BufferedWriter::with_extra(Vec::new(), &header.roots, &header.extra, options)
+                .await
+                .unwrap();
+        for (cid, block) in &blocks {
+            car_writer.write(cid, block).await.unwrap();
+            car_buffered_writer.write(cid, block).await.unwrap();
+        }
+        let car_out = car_writer.finish().await.unwrap().into_inner();
+        let car_buffered_out = car_buffered_writer.finish().await.unwrap();
+
+        // Seekable and non-seekable outputs must be identical
+        assert_eq!(hex::encode(&car_out), hex::encode(&car_buffered_out));
+
+        (blocks, car_out)
+    }
+
+    fn carv2_header_of(car: &[u8]) -> CarV2Header {
+        assert_eq!(car[..CARV2_PRAGMA_SIZE], CARV2_PRAGMA);
+        let header_buf = car[CARV2_PRAGMA_SIZE..CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE]
+            .try_into()
+            .unwrap();
+        decode_carv2_header(header_buf).unwrap()
+    }
+
+    #[test]
+    fn write_carv2_basic() {
+        executor::block_on(async {
+            let car_filepath = "./tests/spec_fixtures/carv2-basic.car";
+            let (blocks, car_out) = write_carv2(car_filepath, Default::default()).await;
+
+            // Same header, and same blocks on read back
+            let car = std::fs::read(car_filepath).unwrap();
+            assert_eq!(carv2_header_of(&car_out), carv2_header_of(&car));
+            let (blocks_out, _) = car_read_all(&mut car_out.as_slice(), true).await.unwrap();
+            assert_eq!(blocks_out, blocks);
+
+            // The spec fixture index is IndexSorted without the multicodec prefix 0x0400
+            assert_eq!(&car_out[499..501], &[0x80, 0x08]);
+            assert_eq!(hex::encode(&car_out[501..]), hex::encode(&car[499..]));
+        })
+    }
+
+    #[test]
+    fn write_carv2_padding() {
+        executor::block_on(async {
+            let options = CarV2WriterOptions {
+                data_padding: 7,
+                index_padding: 3,
+                ..Default::default()
+            };
+            let (blocks, car_out) =
+                write_carv2("./tests/spec_fixtures/carv1-basic.car", options).await;
+
+            let header = carv2_header_of(&car_out);
+            let data_offset = (CARV2_PRAGMA_SIZE + CARV2_HEADER_SIZE + 7) as u64;
+            assert_eq!(header.data_offset, data_offset);
+            assert_eq!(header.index_offset, data_offset + header.data_size + 3);
+            assert_eq!(
+                &car_out[header.index_offset as usize..header.index_offset as usize + 2],
+                &[0x80, 0x08]
+            );
+
+            let (blocks_out, _) = car_read_all(&mut car_out.as_slice(), true).await.unwrap();
+            assert_eq!(blocks_out, blocks);
+        })
+    }
+
+    #[test]
+    fn write_carv2_multihash_index() {
+        executor::block_on(async {
+            let options = CarV2WriterOptions {
+                index_codec: CarIndexCodec::MultihashSorted,
+                ..Default::default()
+            };
+            let (_, car_out) = write_carv2("./tests/go_car_fixtures/sample-v1.car", options).await;
+
+            // Same index as go-car
+            let header = carv2_header_of(&car_out);
+            let index =
+                std::fs::read("./tests/go_car_fixtures/sample-multihash-index-sorted.carindex")
+                    .unwrap();
+            assert_eq!(
+                hex::encode(&car_out[header.index_offset as usize..]),
+                hex::encode(index)
+            );
+        })
+    }
+
+    #[test]
+    fn write_carv2_omit_index() {
+        executor::block_on(async {
+            let options = CarV2WriterOptions {
+                omit_index: true,
+                ..Default::default()
+            };
+            let (blocks, car_out) =
+                write_carv2("./tests/go_car_fixtures/sample-v1.car", options).await;
+
+            let header = carv2_header_of(&car_out);
+            assert_eq!(header.index_offset, 0);
+            assert_eq!(car_out.len() as u64, header.data_offset + header.data_size);
+
+            let (blocks_out, _) = car_read_all(&mut car_out.as_slice(), true).await.unwrap();
+            assert_eq!(blocks_out, blocks);
+        })
+    }
+}
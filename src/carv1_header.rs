@@ -1,12 +1,16 @@
+use std::collections::BTreeMap;
+
 use ipld_core::{cid::Cid, codec::Codec, ipld::Ipld};
 use serde_ipld_dagcbor::codec::DagCborCodec;
 
-use crate::error::CarDecodeError;
+use crate::error::{CarDecodeError, CarEncodeError, CarV1HeaderError};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Default)]
 pub(crate) struct CarV1Header {
     pub version: u64,
     pub roots: Option<Vec<Cid>>,
+    /// Keys other than `roots` and `version`
+    pub extra: BTreeMap<String, Ipld>,
 }
 
 /// CARv1 header structure
@@ -16,18 +20,16 @@ pub(crate) struct CarV1Header {
 /// [varint][DAG-CBOR block][varint|CID|block][varint|CID|block]
 /// ```
 pub(crate) fn decode_carv1_header(header: &[u8]) -> Result<CarV1Header, CarDecodeError> {
-    let header: Ipld = DagCborCodec::decode(header).map_err(|e| {
-        CarDecodeError::InvalidCarV1Header(format!("header cbor codec error: {e:?}"))
-    })?;
+    let header: Ipld = DagCborCodec::decode(header).map_err(CarV1HeaderError::Cbor)?;
 
     // {"roots": [QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf], "version": 1}
-    let header = if let Ipld::Map(map) = header {
+    let mut header = if let Ipld::Map(map) = header {
         map
     } else {
-        return Err(CarDecodeError::InvalidCarV1Header(format!(
-            "header expected cbor Map but got {:#?}",
-            header
-        )));
+        return Err(CarV1HeaderError::NotAMap {
+            kind: header.kind(),
+        }
+        .into());
     };
 
     let roots = match header.get("roots") {
@@ -37,20 +39,12 @@ pub(crate) fn decode_carv1_header(header: &[u8]) -> Result<CarV1Header, CarDecod
                 if let Ipld::Link(cid) = root {
                     roots.push(*cid);
                 } else {
-                    return Err(CarDecodeError::InvalidCarV1Header(format!(
-                        "roots key elements expected cbor Link but got {:#?}",
-                        root
-                    )));
+                    return Err(CarV1HeaderError::RootNotALink { kind: root.kind() }.into());
                 }
             }
             Some(roots)
         }
-        Some(ipld) => {
-            return Err(CarDecodeError::InvalidCarV1Header(format!(
-                "roots key expected cbor List but got {:#?}",
-                ipld
-            )))
-        }
+        Some(ipld) => return Err(CarV1HeaderError::RootsNotAList { kind: ipld.kind() }.into()),
         // CARv2 does not have 'roots' key, so allow to not be specified
         None => None,
     };
@@ -58,25 +52,54 @@ pub(crate) fn decode_carv1_header(header: &[u8]) -> Result<CarV1Header, CarDecod
     let version = match header.get("version") {
         Some(Ipld::Integer(int)) => *int as u64,
         Some(ipld) => {
-            return Err(CarDecodeError::InvalidCarV1Header(format!(
-                "version key expected cbor Integer but got {:#?}",
-                ipld
-            )))
-        }
-        None => {
-            return Err(CarDecodeError::InvalidCarV1Header(format!(
-                "expected header key version, keys: {:?}",
-                header.keys().collect::<Vec<&String>>()
-            )))
+            return Err(CarV1HeaderError::VersionNotAnInteger { kind: ipld.kind() }.into())
         }
+        None => return Err(CarV1HeaderError::MissingVersion.into()),
     };
 
-    Ok(CarV1Header { version, roots })
+    header.remove("roots");
+    header.remove("version");
+
+    Ok(CarV1Header {
+        version,
+        roots,
+        extra: header,
+    })
+}
+
+/// Encodes a CARv1 header as a DAG-CBOR block, without the leading varint.
+/// Inverse of [`decode_carv1_header`].
+pub(crate) fn encode_carv1_header(header: &CarV1Header) -> Result<Vec<u8>, CarEncodeError> {
+    if let Some(key) = ["roots", "version"]
+        .into_iter()
+        .find(|key| header.extra.contains_key(*key))
+    {
+        return Err(CarEncodeError::InvalidCarV1Header {
+            reason: CarV1HeaderError::ReservedKey {
+                key: key.to_owned(),
+            },
+        });
+    }
+
+    let mut map = header.extra.clone();
+    if let Some(roots) = &header.roots {
+        map.insert(
+            "roots".to_string(),
+            Ipld::List(roots.iter().map(|root| Ipld::Link(*root)).collect()),
+        );
+    }
+    map.insert("version".to_string(), Ipld::Integer(header.version.into()));
+
+    DagCborCodec::encode_to_vec(&Ipld::Map(map)).map_err(|err| CarEncodeError::InvalidCarV1Header {
+        reason: CarV1HeaderError::Cbor(err),
+    })
 }
 
 #[cfg(test)]
 mod tests {
 
+    use ipld_core::ipld::IpldKind;
+
     use super::*;
     use crate::{carv2_header::CARV2_PRAGMA, *};
 
@@ -89,7 +112,8 @@ mod tests {
             decode_carv1_header(&header_buf).unwrap(),
             CarV1Header {
                 version: 1,
-                roots: Some(vec!(cid))
+                roots: Some(vec!(cid)),
+                ..Default::default()
             }
         )
     }
@@ -99,10 +123,34 @@ mod tests {
         let header_buf = hex::decode("a265726f6f747371d82a58230012205b0995ced69229d26009c53c185a62ea805a339383521edbed1028c4966154486776657273696f6e01").unwrap();
 
         match decode_carv1_header(&header_buf) {
-            Err(CarDecodeError::InvalidCarV1Header(str)) => assert_eq!(
-                str,
-                "header cbor codec error: DecodeIo(InvalidUtf8(Utf8Error { valid_up_to: 0, error_len: Some(1) }))"
-            ),
+            Err(
+                err @ CarDecodeError::InvalidCarV1Header {
+                    reason: CarV1HeaderError::Cbor(_),
+                    ..
+                },
+            ) => {
+                // The codec detail is only in the source, not repeated in the message
+                let source = std::error::Error::source(&err).unwrap().to_string();
+                assert_eq!(
+                    err.to_string(),
+                    "invalid CARv1 header: header is not valid dag-cbor"
+                );
+                assert!(source.contains("InvalidUtf8"), "{}", source);
+            }
+            x => panic!("other result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn decode_carv1_header_error_not_a_map() {
+        // DAG-CBOR list [1]
+        let header_buf = hex::decode("8101").unwrap();
+
+        match decode_carv1_header(&header_buf) {
+            Err(CarDecodeError::InvalidCarV1Header {
+                reason: CarV1HeaderError::NotAMap { kind },
+                ..
+            }) => assert!(matches!(kind, IpldKind::List)),
             x => panic!("other result {:?}", x),
         }
     }
@@ -112,13 +160,73 @@ mod tests {
         let header_buf = hex::decode("0000").unwrap();
 
         match decode_carv1_header(&header_buf) {
-            Err(CarDecodeError::InvalidCarV1Header(str)) => {
-                assert_eq!(str, "header cbor codec error: DecodeIo(TrailingData)")
-            }
+            Err(CarDecodeError::InvalidCarV1Header {
+                reason: CarV1HeaderError::Cbor(_),
+                ..
+            }) => {}
+            x => panic!("other result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn encode_carv1_header_basic() {
+        let cid = Cid::try_from("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf").unwrap();
+        let header = CarV1Header {
+            version: 1,
+            roots: Some(vec![cid]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            hex::encode(encode_carv1_header(&header).unwrap()),
+            "a265726f6f747381d82a58230012205b0995ced69229d26009c53c185a62ea805a339383521edbed1028c4966154486776657273696f6e01"
+        )
+    }
+
+    #[test]
+    fn carv1_header_extra_roundtrip() {
+        let cid = Cid::try_from("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf").unwrap();
+        let header = CarV1Header {
+            version: 1,
+            roots: Some(vec![cid]),
+            extra: BTreeMap::from([
+                ("dups".to_string(), Ipld::Bool(true)),
+                ("producer".to_string(), Ipld::String("rs-car".to_string())),
+            ]),
+        };
+
+        let header_buf = encode_carv1_header(&header).unwrap();
+        assert_eq!(decode_carv1_header(&header_buf).unwrap(), header);
+    }
+
+    #[test]
+    fn encode_carv1_header_error_reserved_extra_key() {
+        let header = CarV1Header {
+            version: 1,
+            roots: Some(vec![]),
+            extra: BTreeMap::from([("version".to_string(), Ipld::Integer(2))]),
+        };
+
+        match encode_carv1_header(&header) {
+            Err(CarEncodeError::InvalidCarV1Header {
+                reason: CarV1HeaderError::ReservedKey { key },
+            }) => assert_eq!(key, "version"),
             x => panic!("other result {:?}", x),
         }
     }
 
+    #[test]
+    fn encode_carv1_header_v2_pragma() {
+        let header = CarV1Header {
+            version: 2,
+            roots: None,
+            ..Default::default()
+        };
+
+        // First byte is the varint length
+        assert_eq!(encode_carv1_header(&header).unwrap(), &CARV2_PRAGMA[1..])
+    }
+
     #[test]
     fn decode_carv1_header_v2_pragma() {
         assert_eq!(
@@ -126,7 +234,8 @@ mod tests {
             decode_carv1_header(&CARV2_PRAGMA[1..]).unwrap(),
             CarV1Header {
                 version: 2,
-                roots: None
+                roots: None,
+                ..Default::default()
             }
         )
     }
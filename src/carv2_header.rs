@@ -1,3 +1,5 @@
+use std::ops::BitOr;
+
 use crate::error::CarDecodeError;
 
 pub(crate) const CARV2_HEADER_SIZE: usize = 40;
@@ -5,7 +7,6 @@ pub(crate) const CARV2_PRAGMA_SIZE: usize = 11;
 
 // The pragma of a CARv2, containing the version number.
 // This is a valid CARv1 header, with version number of 2 and no root CIDs.
-#[allow(dead_code)]
 pub(crate) const CARV2_PRAGMA: [u8; CARV2_PRAGMA_SIZE] = [
     0x0a, // unit(10)
     0xa1, // map(1)
@@ -14,14 +15,78 @@ pub(crate) const CARV2_PRAGMA: [u8; CARV2_PRAGMA_SIZE] = [
     0x02, // uint(2)
 ];
 
-#[derive(Debug, PartialEq)]
-pub(crate) struct CarV2Header {
-    pub characteristics: u128,
+/// Fixed size header of a CARv2 after its pragma, with the locations of the CARv1
+/// data payload and of the index
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CarV2Header {
+    pub characteristics: Characteristics,
+    /// Byte offset of the CARv1 data payload from the start of the CARv2
     pub data_offset: u64,
+    /// Byte length of the CARv1 data payload
     pub data_size: u64,
+    /// Byte offset of the index from the start of the CARv2, 0 if there is no index
     pub index_offset: u64,
 }
 
+impl CarV2Header {
+    /// Returns true if the CARv2 has an index
+    pub fn has_index(&self) -> bool {
+        self.index_offset != 0
+    }
+}
+
+/// 128 bit field of features of a CARv2, see [`CarV2Header::characteristics`]
+///
+/// # Examples
+/// ```
+/// use rs_car::Characteristics;
+///
+/// let characteristics = Characteristics::from_bits(1 << 127);
+/// assert!(characteristics.is_fully_indexed());
+/// assert_eq!(characteristics, Characteristics::FULLY_INDEXED);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Hash)]
+pub struct Characteristics(u128);
+
+impl Characteristics {
+    /// The index has an entry for every block, including blocks with identity CIDs.
+    /// Leftmost bit of the field.
+    pub const FULLY_INDEXED: Characteristics = Characteristics(1 << 127);
+
+    /// No characteristics
+    pub const fn empty() -> Self {
+        Characteristics(0)
+    }
+
+    /// Characteristics of `bits`, unknown bits are kept as is
+    pub const fn from_bits(bits: u128) -> Self {
+        Characteristics(bits)
+    }
+
+    /// Raw bits of the field, as the big endian integer of the CARv2 header
+    pub const fn bits(&self) -> u128 {
+        self.0
+    }
+
+    /// Returns true if all bits of `other` are set
+    pub const fn contains(&self, other: Characteristics) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns true if [`Characteristics::FULLY_INDEXED`] is set
+    pub const fn is_fully_indexed(&self) -> bool {
+        self.contains(Characteristics::FULLY_INDEXED)
+    }
+}
+
+impl BitOr for Characteristics {
+    type Output = Characteristics;
+
+    fn bitor(self, rhs: Characteristics) -> Characteristics {
+        Characteristics(self.0 | rhs.0)
+    }
+}
+
 /// CARv2 header consists of:
 /// - 11-byte pragma
 /// - 40-byte header with characteristics and locations
@@ -39,7 +104,8 @@ pub(crate) fn decode_carv2_header(
     // 2. Data offset: A 64-bit (8-byte) unsigned little-endian integer indicating the byte-offset from the beginning of the CARv2 to the first byte of the CARv1 data payload.
     // 3. Data size: A 64-bit (8-byte) unsigned little-endian integer indicating the byte-length of the CARv1 data payload.
     // 4. Index offset: A 64-bit (8-byte) unsigned little-endian integer indicating the byte-offset from the beginning of the CARv2 to the first byte of the index payload. This value may be 0 to indicate the absence of index data.
-    let characteristics = u128::from_be_bytes(header[0..16].try_into().unwrap());
+    let characteristics =
+        Characteristics::from_bits(u128::from_be_bytes(header[0..16].try_into().unwrap()));
     let data_offset = u64::from_le_bytes(header[16..24].try_into().unwrap());
     let data_size = u64::from_le_bytes(header[24..32].try_into().unwrap());
     let index_offset = u64::from_le_bytes(header[32..40].try_into().unwrap());
@@ -51,3 +117,59 @@ pub(crate) fn decode_carv2_header(
         index_offset,
     })
 }
+
+/// Encodes a CARv2 header, inverse of [`decode_carv2_header`]
+pub(crate) fn encode_carv2_header(header: &CarV2Header) -> [u8; CARV2_HEADER_SIZE] {
+    let mut buf = [0u8; CARV2_HEADER_SIZE];
+    buf[0..16].copy_from_slice(&header.characteristics.bits().to_be_bytes());
+    buf[16..24].copy_from_slice(&header.data_offset.to_le_bytes());
+    buf[24..32].copy_from_slice(&header.data_size.to_le_bytes());
+    buf[32..40].copy_from_slice(&header.index_offset.to_le_bytes());
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carv2_header_basic_identity() {
+        // v2 header of ./tests/spec_fixtures/carv2-basic.car
+        let header_buf: [u8; CARV2_HEADER_SIZE] = hex::decode(
+            "000000000000000000000000000000003300000000000000c001000000000000f301000000000000",
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+        let header = decode_carv2_header(&header_buf).unwrap();
+        assert_eq!(
+            header,
+            CarV2Header {
+                characteristics: Characteristics::empty(),
+                data_offset: 51,
+                data_size: 448,
+                index_offset: 499,
+            }
+        );
+        assert_eq!(encode_carv2_header(&header), header_buf);
+        assert!(header.has_index());
+    }
+
+    #[test]
+    fn carv2_header_fully_indexed() {
+        let mut header_buf = [0u8; CARV2_HEADER_SIZE];
+        header_buf[0] = 0x80;
+
+        let header = decode_carv2_header(&header_buf).unwrap();
+        assert_eq!(header.characteristics, Characteristics::FULLY_INDEXED);
+        assert!(header.characteristics.is_fully_indexed());
+        assert!(!header.has_index());
+        assert_eq!(encode_carv2_header(&header), header_buf);
+
+        let other = Characteristics::from_bits(1);
+        assert!(!other.is_fully_indexed());
+        assert!((other | Characteristics::FULLY_INDEXED).contains(other));
+        assert!((other | Characteristics::FULLY_INDEXED).is_fully_indexed());
+    }
+}
@@ -1,36 +1,621 @@
-use std::io;
+use std::{fmt, io};
 
-use ipld_core::cid::{self, multihash, Cid};
+use ipld_core::{
+    cid::{self, multihash, Cid},
+    ipld::IpldKind,
+};
+use serde_ipld_dagcbor::error::CodecError;
 
+/// Error decoding a CAR stream. `location` is the position of the failing header part or
+/// section in the CAR stream, when known.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum CarDecodeError {
-    InvalidCarV1Header(String),
-    InvalidCarV2Header(String),
-    InvalidMultihash(String),
-    InvalidCid(String),
-    InvalidBlockHeader(String),
-    BlockDigestMismatch(String),
-    UnsupportedHashCode((HashCode, Cid)),
-    BlockStartEOF,
-    UnsupportedCarVersion { version: u64 },
-    IoError(io::Error),
+    InvalidCarV1Header {
+        reason: CarV1HeaderError,
+        location: Option<Location>,
+    },
+    /// The data payload and index offsets of a CARv2 header do not fit the stream
+    InvalidCarV2Header {
+        reason: CarV2HeaderError,
+        data_offset: u64,
+        data_size: u64,
+        index_offset: u64,
+        location: Option<Location>,
+    },
+    InvalidMultihash {
+        reason: MultihashError,
+        location: Option<Location>,
+    },
+    InvalidCid {
+        source: cid::Error,
+        location: Option<Location>,
+    },
+    InvalidBlockHeader {
+        reason: BlockHeaderError,
+        location: Option<Location>,
+    },
+    InvalidIndex {
+        reason: IndexError,
+        location: Option<Location>,
+    },
+    /// The digest of a block does not match its CID
+    BlockDigestMismatch {
+        mismatch: Box<DigestMismatch>,
+        location: Option<Location>,
+    },
+    /// No hash function is registered for the multihash code of a block CID
+    UnsupportedHashCode {
+        code: u64,
+        cid: Box<Cid>,
+        location: Option<Location>,
+    },
+    /// A value of the stream is over its limit in [`crate::CarReaderOptions`]
+    LimitExceeded {
+        limit: Limit,
+        value: u64,
+        max: u64,
+        location: Option<Location>,
+    },
+    /// Deviation from the CARv1 or CARv2 specs, only rejected by [`crate::Strictness::Strict`]
+    SpecViolation {
+        violation: SpecViolation,
+        location: Option<Location>,
+    },
+    BlockStartEOF {
+        location: Option<Location>,
+    },
+    UnsupportedCarVersion {
+        version: u64,
+        location: Option<Location>,
+    },
+    UnsupportedIndexCodec {
+        codec: u64,
+        location: Option<Location>,
+    },
+    IoError {
+        source: io::Error,
+        location: Option<Location>,
+    },
 }
 
+/// Position of a failing header part or section in the CAR stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    /// Absolute byte offset of the failing header part or section, from the start of the
+    /// CAR stream
+    pub offset: u64,
+    /// Ordinal of the failing section from 0. `None` for the header, and for sections read
+    /// through the CARv2 index.
+    pub section_index: Option<u64>,
+}
+
+impl CarDecodeError {
+    /// Position of the header part or section that failed to decode, if known
+    pub fn location(&self) -> Option<Location> {
+        match self {
+            CarDecodeError::InvalidCarV1Header { location, .. }
+            | CarDecodeError::InvalidCarV2Header { location, .. }
+            | CarDecodeError::InvalidMultihash { location, .. }
+            | CarDecodeError::InvalidCid { location, .. }
+            | CarDecodeError::InvalidBlockHeader { location, .. }
+            | CarDecodeError::InvalidIndex { location, .. }
+            | CarDecodeError::BlockDigestMismatch { location, .. }
+            | CarDecodeError::UnsupportedHashCode { location, .. }
+            | CarDecodeError::LimitExceeded { location, .. }
+            | CarDecodeError::SpecViolation { location, .. }
+            | CarDecodeError::BlockStartEOF { location }
+            | CarDecodeError::UnsupportedCarVersion { location, .. }
+            | CarDecodeError::UnsupportedIndexCodec { location, .. }
+            | CarDecodeError::IoError { location, .. } => *location,
+        }
+    }
+
+    /// Absolute byte offset of the header part or section that failed to decode, if known
+    pub fn offset(&self) -> Option<u64> {
+        self.location().map(|location| location.offset)
+    }
+
+    /// Ordinal from 0 of the section that failed to decode, if known
+    pub fn section_index(&self) -> Option<u64> {
+        self.location()?.section_index
+    }
+
+    /// Attaches a location to the error, unless it already has one
+    pub(crate) fn located(mut self, offset: u64, section_index: Option<u64>) -> Self {
+        let location = match &mut self {
+            CarDecodeError::InvalidCarV1Header { location, .. }
+            | CarDecodeError::InvalidCarV2Header { location, .. }
+            | CarDecodeError::InvalidMultihash { location, .. }
+            | CarDecodeError::InvalidCid { location, .. }
+            | CarDecodeError::InvalidBlockHeader { location, .. }
+            | CarDecodeError::InvalidIndex { location, .. }
+            | CarDecodeError::BlockDigestMismatch { location, .. }
+            | CarDecodeError::UnsupportedHashCode { location, .. }
+            | CarDecodeError::LimitExceeded { location, .. }
+            | CarDecodeError::SpecViolation { location, .. }
+            | CarDecodeError::BlockStartEOF { location }
+            | CarDecodeError::UnsupportedCarVersion { location, .. }
+            | CarDecodeError::UnsupportedIndexCodec { location, .. }
+            | CarDecodeError::IoError { location, .. } => location,
+        };
+        location.get_or_insert(Location {
+            offset,
+            section_index,
+        });
+        self
+    }
+}
+
+/// Digests of a block that does not match its CID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestMismatch {
+    pub cid: Cid,
+    /// Multihash code of the hash function
+    pub code: u64,
+    /// Name of the hash function, from [`crate::MultihashVerifier::name()`]
+    pub hash: String,
+    /// Digest of the CID
+    pub expected: Vec<u8>,
+    /// Digest of the block data
+    pub actual: Vec<u8>,
+}
+
+/// Reasons of [`CarDecodeError::InvalidCarV1Header`] and [`CarEncodeError::InvalidCarV1Header`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CarV1HeaderError {
+    /// The header is not valid DAG-CBOR, the codec error is the
+    /// [`std::error::Error::source()`]
+    Cbor(CodecError),
+    /// The header varint is longer than a u64
+    InvalidVarint,
+    /// The header is not a map
+    NotAMap { kind: IpldKind },
+    /// The `roots` key is not a list
+    RootsNotAList { kind: IpldKind },
+    /// An element of the `roots` list is not a link
+    RootNotALink { kind: IpldKind },
+    /// The `version` key is not an integer
+    VersionNotAnInteger { kind: IpldKind },
+    /// The header has no `version` key
+    MissingVersion,
+    /// The header of a CARv1 has no `roots` key
+    MissingRoots,
+    /// An extra key is `roots` or `version`, only written from the header fields
+    ReservedKey { key: String },
+}
+
+/// Reasons of [`CarDecodeError::InvalidCarV2Header`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CarV2HeaderError {
+    /// The data payload starts before the end of the CARv2 header. Rejected in lenient
+    /// mode too, as the payload can not be located
+    DataOffsetBeforeHeaderEnd,
+    /// Data offset + data size overflows a u64
+    DataEndOverflow,
+    /// The index starts before the end of the CARv2 header
+    IndexOffsetBeforeHeaderEnd,
+    /// The index starts before the data payload and runs over it to the end of the stream
+    IndexOverlapsData,
+    /// The index starts inside the data payload
+    IndexInsideData,
+    /// The data payload ends inside its CARv1 header of `header_v1_len` bytes
+    DataEndInsideHeader { header_v1_len: u64 },
+    /// The data payload ends inside its last section
+    DataEndInsideSection,
+    /// The stream ends at a section boundary before the end of the data payload
+    DataEndPastStream,
+}
+
+/// Reasons of [`CarDecodeError::InvalidMultihash`]
 #[derive(Debug)]
-pub enum HashCode {
-    Code(u64),
+#[non_exhaustive]
+pub enum MultihashError {
+    /// The code varint is longer than a u64
+    CodeVarint,
+    /// The digest size varint is longer than a u64
+    SizeVarint,
+    /// The digest is larger than the max supported digest size
+    DigestSize {
+        size: u64,
+        max: u64,
+    },
+    Multihash(multihash::Error),
+}
+
+/// Reasons of [`CarDecodeError::InvalidBlockHeader`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BlockHeaderError {
+    /// The section length varint is longer than a u64
+    InvalidVarint,
+    /// The section length is zero
+    ZeroLength,
+    /// The CID runs past the end of its section of `section_len` bytes
+    CidPastSection { section_len: u64 },
+}
+
+/// Reasons of [`CarDecodeError::InvalidIndex`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum IndexError {
+    /// The codec varint is longer than a u64
+    CodecVarint,
+    /// The index ends before a field of `expected` bytes, with `left` bytes left
+    UnexpectedEnd {
+        expected: u64,
+        left: u64,
+    },
+    /// Bytes after the last bucket
+    TrailingBytes {
+        len: u64,
+    },
+    NegativeBucketCount {
+        count: i32,
+    },
+    /// A bucket entry is narrower than its 8-byte offset
+    BucketWidth {
+        width: u32,
+        min: u32,
+    },
+    /// The entries of a bucket are not a whole number of entries
+    BucketEntriesLen {
+        len: u64,
+        width: u32,
+    },
+    DuplicateBucketWidth {
+        width: u32,
+    },
+    NegativeCodeCount {
+        count: i32,
+    },
+    DuplicateCode {
+        code: u64,
+    },
+    /// The CAR has no index to read
+    NoIndex {
+        version: u64,
+    },
+    /// An index offset points outside of the sections of the data payload
+    SectionOffsetOutOfRange {
+        offset: u64,
+    },
+    /// The section at an index offset has a different multihash than the indexed one
+    SectionMismatch {
+        cid: Box<Cid>,
+    },
+    /// The stream position of the index is unknown after a section error or a previous read
+    PositionUnknown,
+}
+
+/// Deviations from the CARv1 and CARv2 specs rejected by [`crate::Strictness::Strict`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SpecViolation {
+    /// A varint, or a CID with varints, is longer than its minimal encoding
+    NonMinimalVarint { len: usize, minimal_len: usize },
+    /// Characteristics bits of a CARv2 header other than the ones in the spec, as the
+    /// big-endian bytes of the header to keep the error small
+    UnknownCharacteristics { bits: [u8; 16] },
+    /// The stream ends inside a section varint after `len` bytes, a clean end in
+    /// lenient mode
+    TruncatedSectionVarint { len: usize },
+    /// A CAR header key other than `version`, and `roots` in a CARv1 header
+    UnknownHeaderKey { key: String },
+    /// A root is listed more than once in the CAR header
+    DuplicateRoot { cid: Cid },
+    /// The CAR header has no roots
+    EmptyRoots,
+    /// The CARv1 header inside a CARv2 has a version other than 1
+    InnerHeaderVersion { version: u64 },
+}
+
+/// Limits of [`crate::CarReaderOptions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Limit {
+    /// [`crate::CarReaderOptions::max_header_len`]
+    HeaderLen,
+    /// [`crate::CarReaderOptions::max_block_len`]
+    BlockLen,
+    /// [`crate::CarReaderOptions::max_padding_len`]
+    PaddingLen,
+    /// [`crate::CarReaderOptions::max_roots`]
+    Roots,
+    /// [`crate::CarReaderOptions::max_total_bytes`]
+    TotalBytes,
+    /// [`crate::CarReaderOptions::max_index_len`], the value is the bytes read before
+    /// giving up, not the full index length
+    IndexLen,
+    /// Sections of a CARv2 data payload longer than the platform `usize`
+    DataSize,
+    /// Bytes pushed to a [`crate::CarDecoder`] and not decoded yet, over the largest
+    /// header or section allowed by the other limits
+    BufferedLen,
+}
+
+impl fmt::Display for CarDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CarDecodeError::InvalidCarV1Header { reason, .. } => {
+                write!(f, "invalid CARv1 header: {reason}")
+            }
+            CarDecodeError::InvalidCarV2Header {
+                reason,
+                data_offset,
+                data_size,
+                index_offset,
+                ..
+            } => write!(
+                f,
+                "invalid CARv2 header: {reason} (data offset {data_offset}, data size {data_size}, index offset {index_offset})"
+            ),
+            CarDecodeError::InvalidMultihash { reason, .. } => {
+                write!(f, "invalid multihash: {reason}")
+            }
+            CarDecodeError::InvalidCid { source, .. } => write!(f, "invalid CID: {source}"),
+            CarDecodeError::InvalidBlockHeader { reason, .. } => {
+                write!(f, "invalid section header: {reason}")
+            }
+            CarDecodeError::InvalidIndex { reason, .. } => {
+                write!(f, "invalid CARv2 index: {reason}")
+            }
+            CarDecodeError::BlockDigestMismatch { mismatch, .. } => write!(
+                f,
+                "{} (multihash 0x{:x}) digest mismatch for block {}: expected {} but got {}",
+                mismatch.hash,
+                mismatch.code,
+                mismatch.cid,
+                to_hex_lower(&mismatch.expected),
+                to_hex_lower(&mismatch.actual)
+            ),
+            CarDecodeError::UnsupportedHashCode { code, cid, .. } => {
+                write!(f, "unsupported multihash code 0x{code:x} of block {cid}")
+            }
+            CarDecodeError::LimitExceeded {
+                limit, value, max, ..
+            } => {
+                write!(f, "{limit} {value} exceeds max {max}")
+            }
+            CarDecodeError::SpecViolation { violation, .. } => {
+                write!(f, "spec violation: {violation}")
+            }
+            CarDecodeError::BlockStartEOF { .. } => {
+                write!(f, "end of stream at the start of a section")
+            }
+            CarDecodeError::UnsupportedCarVersion { version, .. } => {
+                write!(f, "unsupported CAR version {version}")
+            }
+            CarDecodeError::UnsupportedIndexCodec { codec, .. } => {
+                write!(f, "unsupported CARv2 index codec 0x{codec:x}")
+            }
+            CarDecodeError::IoError { source, .. } => write!(f, "io error: {source}"),
+        }?;
+
+        match self.location() {
+            Some(Location {
+                offset,
+                section_index: Some(section_index),
+            }) => write!(f, " in section {section_index} at byte {offset}"),
+            Some(Location {
+                offset,
+                section_index: None,
+            }) => write!(f, " at byte {offset}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for CarV1HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CarV1HeaderError::Cbor(_) => write!(f, "header is not valid dag-cbor"),
+            CarV1HeaderError::InvalidVarint => write!(f, "invalid header varint"),
+            CarV1HeaderError::NotAMap { kind } => {
+                write!(f, "header expected cbor map but got {}", kind_name(kind))
+            }
+            CarV1HeaderError::RootsNotAList { kind } => {
+                write!(
+                    f,
+                    "roots key expected cbor list but got {}",
+                    kind_name(kind)
+                )
+            }
+            CarV1HeaderError::RootNotALink { kind } => write!(
+                f,
+                "roots key elements expected cbor link but got {}",
+                kind_name(kind)
+            ),
+            CarV1HeaderError::VersionNotAnInteger { kind } => write!(
+                f,
+                "version key expected cbor integer but got {}",
+                kind_name(kind)
+            ),
+            CarV1HeaderError::MissingVersion => write!(f, "header has no version key"),
+            CarV1HeaderError::MissingRoots => write!(f, "v1 header has no roots key"),
+            CarV1HeaderError::ReservedKey { key } => write!(f, "extra key {key} is reserved"),
+        }
+    }
+}
+
+fn kind_name(kind: &IpldKind) -> &'static str {
+    match kind {
+        IpldKind::Null => "null",
+        IpldKind::Bool => "bool",
+        IpldKind::Integer => "integer",
+        IpldKind::Float => "float",
+        IpldKind::String => "string",
+        IpldKind::Bytes => "bytes",
+        IpldKind::List => "list",
+        IpldKind::Map => "map",
+        IpldKind::Link => "link",
+    }
+}
+
+impl fmt::Display for CarV2HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CarV2HeaderError::DataOffsetBeforeHeaderEnd => {
+                write!(f, "data payload starts before the end of the CARv2 header")
+            }
+            CarV2HeaderError::DataEndOverflow => write!(f, "data payload end overflows"),
+            CarV2HeaderError::IndexOffsetBeforeHeaderEnd => {
+                write!(f, "index starts before the end of the CARv2 header")
+            }
+            CarV2HeaderError::IndexOverlapsData => write!(f, "index overlaps the data payload"),
+            CarV2HeaderError::IndexInsideData => {
+                write!(f, "index starts inside the data payload")
+            }
+            CarV2HeaderError::DataEndInsideHeader { header_v1_len } => write!(
+                f,
+                "data payload ends inside its CARv1 header of {header_v1_len} bytes"
+            ),
+            CarV2HeaderError::DataEndInsideSection => {
+                write!(f, "data payload ends inside the last section")
+            }
+            CarV2HeaderError::DataEndPastStream => {
+                write!(f, "data payload ends past the end of the stream")
+            }
+        }
+    }
+}
+
+impl fmt::Display for MultihashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultihashError::CodeVarint => write!(f, "invalid code varint"),
+            MultihashError::SizeVarint => write!(f, "invalid size varint"),
+            MultihashError::DigestSize { size, max } => {
+                write!(f, "digest size {size} > max {max}")
+            }
+            MultihashError::Multihash(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl fmt::Display for BlockHeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockHeaderError::InvalidVarint => write!(f, "invalid block header varint"),
+            BlockHeaderError::ZeroLength => write!(f, "zero length"),
+            BlockHeaderError::CidPastSection { section_len } => {
+                write!(f, "CID runs past the section length {section_len}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IndexError::CodecVarint => write!(f, "invalid codec varint"),
+            IndexError::UnexpectedEnd { expected, left } => write!(
+                f,
+                "unexpected end of index, expected {expected} bytes but only {left} left"
+            ),
+            IndexError::TrailingBytes { len } => write!(f, "{len} trailing bytes"),
+            IndexError::NegativeBucketCount { count } => {
+                write!(f, "negative bucket count {count}")
+            }
+            IndexError::BucketWidth { width, min } => write!(f, "bucket width {width} < min {min}"),
+            IndexError::BucketEntriesLen { len, width } => {
+                write!(f, "bucket entries len {len} not multiple of width {width}")
+            }
+            IndexError::DuplicateBucketWidth { width } => {
+                write!(f, "duplicate bucket width {width}")
+            }
+            IndexError::NegativeCodeCount { count } => write!(f, "negative code count {count}"),
+            IndexError::DuplicateCode { code } => write!(f, "duplicate multihash code {code}"),
+            IndexError::NoIndex { version } => write!(f, "CARv{version} has no index"),
+            IndexError::SectionOffsetOutOfRange { offset } => {
+                write!(f, "section offset {offset} out of range")
+            }
+            IndexError::SectionMismatch { cid } => {
+                write!(f, "indexed section is a different block {cid}")
+            }
+            IndexError::PositionUnknown => write!(
+                f,
+                "index position unknown after a section error or a previous read"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for SpecViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpecViolation::NonMinimalVarint { len, minimal_len } => {
+                write!(
+                    f,
+                    "non-minimal varint of {len} bytes instead of {minimal_len}"
+                )
+            }
+            SpecViolation::UnknownCharacteristics { bits } => {
+                let bits = u128::from_be_bytes(*bits);
+                write!(f, "unknown CARv2 characteristics bits 0x{bits:x}")
+            }
+            SpecViolation::TruncatedSectionVarint { len } => {
+                write!(f, "stream ends inside a section varint after {len} bytes")
+            }
+            SpecViolation::UnknownHeaderKey { key } => write!(f, "unknown header key {key:?}"),
+            SpecViolation::DuplicateRoot { cid } => write!(f, "duplicate root {cid}"),
+            SpecViolation::EmptyRoots => write!(f, "header has no roots"),
+            SpecViolation::InnerHeaderVersion { version } => {
+                write!(f, "inner CARv1 header version {version}")
+            }
+        }
+    }
 }
 
-impl std::fmt::Display for CarDecodeError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{self:?}")
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Limit::HeaderLen => "header length",
+            Limit::BlockLen => "section length",
+            Limit::PaddingLen => "padding length",
+            Limit::Roots => "root count",
+            Limit::TotalBytes => "total byte length",
+            Limit::IndexLen => "index length",
+            Limit::DataSize => "data payload sections length",
+            Limit::BufferedLen => "buffered length",
+        })
     }
 }
 
+fn to_hex_lower(s: &[u8]) -> String {
+    if s.is_empty() {
+        return "<empty>".to_string();
+    }
+    s.iter().map(|i| format!("{i:02x}")).collect()
+}
+
 impl std::error::Error for CarDecodeError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            CarDecodeError::IoError(err) => Some(err),
+            CarDecodeError::InvalidCarV1Header { reason, .. } => reason.source(),
+            CarDecodeError::InvalidMultihash { reason, .. } => reason.source(),
+            CarDecodeError::InvalidCid { source, .. } => Some(source),
+            CarDecodeError::IoError { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl CarV1HeaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CarV1HeaderError::Cbor(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl MultihashError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MultihashError::Multihash(err) => Some(err),
             _ => None,
         }
     }
@@ -38,18 +623,143 @@ impl std::error::Error for CarDecodeError {
 
 impl From<io::Error> for CarDecodeError {
     fn from(error: io::Error) -> Self {
-        CarDecodeError::IoError(error)
+        CarDecodeError::IoError {
+            source: error,
+            location: None,
+        }
     }
 }
 
 impl From<multihash::Error> for CarDecodeError {
     fn from(error: multihash::Error) -> Self {
-        CarDecodeError::InvalidMultihash(format!("{:?}", error))
+        MultihashError::Multihash(error).into()
     }
 }
 
 impl From<cid::Error> for CarDecodeError {
     fn from(error: cid::Error) -> Self {
-        CarDecodeError::InvalidCid(format!("{:?}", error))
+        CarDecodeError::InvalidCid {
+            source: error,
+            location: None,
+        }
+    }
+}
+
+impl From<SpecViolation> for CarDecodeError {
+    fn from(violation: SpecViolation) -> Self {
+        CarDecodeError::SpecViolation {
+            violation,
+            location: None,
+        }
+    }
+}
+
+impl From<CarV1HeaderError> for CarDecodeError {
+    fn from(reason: CarV1HeaderError) -> Self {
+        CarDecodeError::InvalidCarV1Header {
+            reason,
+            location: None,
+        }
+    }
+}
+
+impl From<MultihashError> for CarDecodeError {
+    fn from(reason: MultihashError) -> Self {
+        CarDecodeError::InvalidMultihash {
+            reason,
+            location: None,
+        }
+    }
+}
+
+impl From<BlockHeaderError> for CarDecodeError {
+    fn from(reason: BlockHeaderError) -> Self {
+        CarDecodeError::InvalidBlockHeader {
+            reason,
+            location: None,
+        }
+    }
+}
+
+impl From<IndexError> for CarDecodeError {
+    fn from(reason: IndexError) -> Self {
+        CarDecodeError::InvalidIndex {
+            reason,
+            location: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CarEncodeError {
+    InvalidCarV1Header { reason: CarV1HeaderError },
+    IoError { source: io::Error },
+}
+
+impl fmt::Display for CarEncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CarEncodeError::InvalidCarV1Header { reason } => {
+                write!(f, "invalid CARv1 header: {reason}")
+            }
+            CarEncodeError::IoError { source } => write!(f, "io error: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for CarEncodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CarEncodeError::InvalidCarV1Header { reason } => reason.source(),
+            CarEncodeError::IoError { source } => Some(source),
+        }
+    }
+}
+
+impl From<io::Error> for CarEncodeError {
+    fn from(error: io::Error) -> Self {
+        CarEncodeError::IoError { source: error }
+    }
+}
+
+/// Error of [`crate::wrap_v1`] and [`crate::extract_v1`], from either side of the
+/// conversion
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CarConvertError {
+    /// Reading the input CAR stream failed
+    Decode(CarDecodeError),
+    /// Writing the output CAR stream failed
+    Encode(CarEncodeError),
+}
+
+impl fmt::Display for CarConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CarConvertError::Decode(err) => write!(f, "decoding the input: {err}"),
+            CarConvertError::Encode(err) => write!(f, "writing the output: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for CarConvertError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CarConvertError::Decode(err) => Some(err),
+            CarConvertError::Encode(err) => Some(err),
+        }
+    }
+}
+
+impl From<CarDecodeError> for CarConvertError {
+    fn from(error: CarDecodeError) -> Self {
+        CarConvertError::Decode(error)
+    }
+}
+
+impl From<CarEncodeError> for CarConvertError {
+    fn from(error: CarEncodeError) -> Self {
+        CarConvertError::Encode(error)
     }
 }
@@ -4,7 +4,22 @@
 //! # Usage
 //!
 //! - To get a block streamer [`CarReader::new()`]
+//! - To set decoding limits, as for untrusted input [`CarReader::with_options()`]
+//! - To verify blocks of other hash functions, register them in [`MultihashVerifierRegistry`]
+//! - To get blocks with their section offsets and lengths [`CarReader::next_section()`]
+//! - To stream big blocks without buffering them [`CarReader::next_block_reader()`]
+//! - To read the CARv2 index after the blocks of a non-seekable stream [`CarReader::read_index()`]
 //! - To read all blocks in memory [car_read_all]
+//! - To decode byte chunks pushed from any source, without IO [`CarDecoder::new()`]
+//! - To read from a blocking [`std::io::Read`] [`sync::CarReader::new()`] or [sync::car_read_all]
+//! - To read and write with tokio, enable the `tokio` feature and use the [`tokio`] module
+//! - To write a CARv1 stream [`CarWriter::new()`], with extra header keys [`CarWriter::with_extra()`]
+//! - To get the locations and [`Characteristics`] of a CARv2 [`CarHeader::header_v2`]
+//! - To get blocks by CID from an indexed CARv2 [`CarIndexedReader::new()`]
+//! - To decode a CARv2 index [`CarIndex::decode()`]
+//! - To build a CARv2 index of an indexless CAR [generate_index]
+//! - To convert a CARv1 into an indexed CARv2 [wrap_v1], and back [extract_v1]
+//! - To write a CARv2 stream [`CarV2Writer::new()`] or [`CarV2BufferedWriter::new()`]
 //!
 
 use std::{
@@ -12,22 +27,47 @@ use std::{
     task::{Context, Poll},
 };
 
-use futures::{future::BoxFuture, AsyncRead, Stream, StreamExt};
-pub use ipld_core::cid::Cid;
-
-use crate::{
-    block_cid::assert_block_cid,
-    car_block::decode_block,
-    car_header::{read_car_header, StreamEnd},
+use futures::{future::poll_fn, AsyncRead, Stream, StreamExt};
+pub use ipld_core::{cid::Cid, ipld::Ipld};
+
+pub use crate::{
+    car_convert::{extract_v1, extract_v1_with_options, wrap_v1, wrap_v1_with_options},
+    car_decoder::{CarDecoder, CarDecoderEvent},
+    car_header::CarHeader,
+    car_index::{CarIndex, CarIndexCodec, IndexSorted, MultihashIndexSorted},
+    car_index_gen::{generate_index, generate_index_with_options},
+    car_indexed_reader::CarIndexedReader,
+    car_reader_options::{CarReaderOptions, Strictness},
+    car_section::{CarBlockReader, CarSection},
+    car_writer::{CarV2BufferedWriter, CarV2Writer, CarV2WriterOptions, CarWriter},
+    carv2_header::{CarV2Header, Characteristics},
+    error::{
+        BlockHeaderError, CarConvertError, CarDecodeError, CarEncodeError, CarV1HeaderError,
+        CarV2HeaderError, DigestMismatch, IndexError, Limit, Location, MultihashError,
+        SpecViolation,
+    },
+    multihash_verifier::{MultihashHasher, MultihashVerifier, MultihashVerifierRegistry},
 };
-pub use crate::{car_header::CarHeader, error::CarDecodeError};
+use crate::{car_header::read_car_header, car_section::SectionStream};
 
 mod block_cid;
 mod car_block;
+mod car_convert;
+mod car_decoder;
 mod car_header;
+mod car_index;
+mod car_index_gen;
+mod car_indexed_reader;
+mod car_reader_options;
+mod car_section;
+mod car_writer;
 mod carv1_header;
 mod carv2_header;
 mod error;
+mod multihash_verifier;
+pub mod sync;
+#[cfg(feature = "tokio")]
+pub mod tokio;
 mod varint;
 
 /// Decodes a CAR stream yielding its blocks and optionally verifying integrity.
@@ -35,20 +75,20 @@ mod varint;
 ///
 /// - To get a block streamer [`CarReader::new()`]
 /// - To read all blocks in memory [car_read_all]
-pub struct CarReader<'a, R> {
-    // r: &'a mut R,
+pub struct CarReader<R> {
+    r: R,
     pub header: CarHeader,
-    read_bytes: usize,
-    validate_block_hash: bool,
-    decode_header_future: Option<DecodeBlockFuture<'a, R>>,
+    sections: SectionStream,
 }
 
-impl<'a, R> CarReader<'a, R>
+impl<R> CarReader<R>
 where
-    R: AsyncRead + Send + Unpin,
+    R: AsyncRead + Unpin,
 {
     /// Decodes a CAR stream up to the header. Returns a `Stream` type that yields
-    /// blocks. The CAR header is available in [`CarReader.header`].
+    /// blocks. The CAR header is available in [`CarReader::header`].
+    ///
+    /// Takes ownership of `r`, pass `&mut r` to keep using it after the stream ends.
     ///
     /// # Examples
     /// ```
@@ -70,18 +110,146 @@ where
     ///   Ok(())
     /// }
     /// ```
-    pub async fn new(
-        r: &'a mut R,
-        validate_block_hash: bool,
-    ) -> Result<CarReader<'a, R>, CarDecodeError> {
-        let header = read_car_header(r).await?;
+    pub async fn new(r: R, validate_block_hash: bool) -> Result<CarReader<R>, CarDecodeError> {
+        Self::with_options(
+            r,
+            CarReaderOptions {
+                validate_block_hash,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Same as [`CarReader::new()`] with custom limits and checks
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::{CarReader, CarReaderOptions};
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let mut r = async_std::fs::File::open("./tests/custom_fixtures/helloworld.car").await?;
+    ///
+    ///   let options = CarReaderOptions::default()
+    ///     .with_max_block_len(1 << 20)
+    ///     .with_max_total_bytes(Some(1 << 30));
+    ///   let car_reader = CarReader::with_options(&mut r, options).await?;
+    ///   println!("{:?}", car_reader.header);
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub async fn with_options(
+        mut r: R,
+        options: CarReaderOptions,
+    ) -> Result<CarReader<R>, CarDecodeError> {
+        let header = read_car_header(&mut r, &options).await?;
         Ok(CarReader {
+            r,
+            sections: SectionStream::new(&header, options),
             header,
-            read_bytes: 0,
-            validate_block_hash,
-            decode_header_future: Some(Box::pin(decode_block(r))),
         })
     }
+
+    /// Returns the next block with the location of its section in the CAR stream.
+    /// Opt-in alternative to the `Stream` items, both advance the same reader.
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::CarReader;
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let mut r = async_std::fs::File::open("./tests/custom_fixtures/helloworld.car").await?;
+    ///
+    ///   let mut car_reader = CarReader::new(&mut r, true).await?;
+    ///
+    ///   while let Some(section) = car_reader.next_section().await {
+    ///     let section = section?;
+    ///     println!("{:?} at byte {}, {} bytes", section.cid, section.offset, section.section_len());
+    ///   }
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub async fn next_section(&mut self) -> Option<Result<CarSection, CarDecodeError>> {
+        poll_fn(|cx| self.poll_next_section(cx)).await
+    }
+
+    /// Returns the next block as an `AsyncRead` instead of buffering it in memory, for
+    /// blocks too big to hold at once. If hash validation is enabled the block is hashed
+    /// as it is read, and a digest mismatch is returned by the read that reaches its end.
+    ///
+    /// Bytes of the block not read are skipped by the next call to any method that
+    /// advances the reader, and still verified.
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::CarReader;
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let mut r = async_std::fs::File::open("./tests/custom_fixtures/helloworld.car").await?;
+    ///
+    ///   let mut car_reader = CarReader::new(&mut r, true).await?;
+    ///
+    ///   while let Some(block_reader) = car_reader.next_block_reader().await {
+    ///     let mut block_reader = block_reader?;
+    ///     println!("{:?} {} bytes", block_reader.cid, block_reader.block_len);
+    ///     futures::io::copy(&mut block_reader, &mut futures::io::sink()).await?;
+    ///   }
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub async fn next_block_reader(
+        &mut self,
+    ) -> Option<Result<CarBlockReader<'_, R>, CarDecodeError>> {
+        self.sections.next_block_reader(&mut self.r).await
+    }
+
+    /// Polling version of [`CarReader::next_section()`]
+    pub fn poll_next_section(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<CarSection, CarDecodeError>>> {
+        self.sections.poll_next_section(cx, &mut self.r)
+    }
+
+    /// Reads the trailing index of a CARv2 in the same pass, without seeking, as for a
+    /// non-seekable stream. Sections not read yet are skipped without decoding them, then
+    /// the padding, and the index is read up to the end of the stream.
+    /// Returns `None` for a CARv1 or a CARv2 without index.
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::CarReader;
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let mut r = async_std::fs::File::open("./tests/go_car_fixtures/sample-unixfs-v2.car").await?;
+    ///
+    ///   let mut car_reader = CarReader::new(&mut r, true).await?;
+    ///   while let Some(section) = car_reader.next_section().await {
+    ///     println!("{:?}", section?.cid);
+    ///   }
+    ///
+    ///   let index = car_reader.read_index().await?;
+    ///   assert!(index.is_some());
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub async fn read_index(&mut self) -> Result<Option<CarIndex>, CarDecodeError> {
+        self.sections.read_index(&mut self.r).await
+    }
+
+    /// Returns the underlying reader. If dropped mid-block, the reader is positioned
+    /// somewhere within that block's section.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
 }
 
 /// Decodes a CAR stream buffering all blocks in memory. For a Stream API use [CarReader].
@@ -105,7 +273,7 @@ where
 ///   Ok(())
 /// }
 /// ```
-pub async fn car_read_all<R: AsyncRead + Unpin + Send>(
+pub async fn car_read_all<R: AsyncRead + Unpin>(
     r: &mut R,
     validate_block_hash: bool,
 ) -> Result<(Vec<(Cid, Vec<u8>)>, CarHeader), CarDecodeError> {
@@ -120,47 +288,16 @@ pub async fn car_read_all<R: AsyncRead + Unpin + Send>(
     Ok((items, decoder.header))
 }
 
-type DecodeBlockFuture<'a, R> =
-    BoxFuture<'a, Result<(&'a mut R, Cid, Vec<u8>, usize), CarDecodeError>>;
-
-impl<'a, R> Stream for CarReader<'a, R>
+impl<R> Stream for CarReader<R>
 where
-    R: AsyncRead + Send + Unpin + 'a,
+    R: AsyncRead + Unpin,
 {
     type Item = Result<(Cid, Vec<u8>), CarDecodeError>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let me = Pin::into_inner(self);
-
-        if let StreamEnd::AfterNBytes(blocks_len) = me.header.eof_stream {
-            if me.read_bytes >= blocks_len {
-                return Poll::Ready(None);
-            }
-        }
-
-        match &mut me.decode_header_future {
-            Some(decode_future) => match decode_future.as_mut().poll(cx) {
-                Poll::Pending => Poll::Pending,
-                Poll::Ready(Ok((r, cid, block, block_len))) => {
-                    if me.validate_block_hash {
-                        assert_block_cid(&cid, &block)?;
-                    }
-                    me.read_bytes += block_len;
-                    me.decode_header_future = Some(Box::pin(decode_block(r)));
-                    Poll::Ready(Some(Ok((cid, block))))
-                }
-                Poll::Ready(Err(CarDecodeError::BlockStartEOF))
-                    if me.header.eof_stream == StreamEnd::OnBlockEOF =>
-                {
-                    Poll::Ready(None)
-                }
-                Poll::Ready(Err(err)) => {
-                    me.decode_header_future = None;
-                    Poll::Ready(Some(Err(err)))
-                }
-            },
-            None => Poll::Ready(None),
-        }
+        Pin::into_inner(self)
+            .poll_next_section(cx)
+            .map(|item| item.map(|section| section.map(|s| (s.cid, s.block))))
     }
 }
 
@@ -311,6 +448,144 @@ mod tests {
         })
     }
 
+    /// Opens a CAR file and returns its reader, which owns the file
+    async fn open_car(car_filepath: &str) -> CarReader<async_std::fs::File> {
+        let file = async_std::fs::File::open(car_filepath).await.unwrap();
+        CarReader::new(file, true).await.unwrap()
+    }
+
+    #[test]
+    fn decode_owned_reader_into_inner() {
+        executor::block_on(async {
+            let car_filepath = "./tests/spec_fixtures/carv1-basic.car";
+            let mut car_reader = open_car(car_filepath).await;
+
+            let mut blocks = vec![];
+            while let Some(item) = car_reader.next().await {
+                blocks.push(item.unwrap());
+            }
+
+            let car = std::fs::read(car_filepath).unwrap();
+            let (expected_blocks, _) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+            assert_eq!(blocks, expected_blocks);
+
+            // Reader is returned at the end of the stream
+            let mut file = car_reader.into_inner();
+            let mut rest = vec![];
+            futures::AsyncReadExt::read_to_end(&mut file, &mut rest)
+                .await
+                .unwrap();
+            assert!(rest.is_empty());
+        })
+    }
+
+    /// Returns `Poll::Pending` before every read, and reads at most 3 bytes at once
+    struct PendingReader<'a> {
+        buf: &'a [u8],
+        pending: bool,
+    }
+
+    impl<'a> AsyncRead for PendingReader<'a> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = buf.len().min(self.buf.len()).min(3);
+            buf[..n].copy_from_slice(&self.buf[..n]);
+            self.buf = &self.buf[n..];
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    #[test]
+    fn decode_resumes_after_pending() {
+        executor::block_on(async {
+            for car_filepath in [
+                "./tests/spec_fixtures/carv1-basic.car",
+                "./tests/spec_fixtures/carv2-basic.car",
+                "./tests/go_car_fixtures/sample-v1-tailing-corrupt-section.car",
+            ] {
+                let car = std::fs::read(car_filepath).unwrap();
+                let expected = car_read_all(&mut car.as_slice(), true)
+                    .await
+                    .map(|(blocks, _)| blocks)
+                    .map_err(|err| err.to_string());
+
+                let mut r = PendingReader {
+                    buf: &car,
+                    pending: false,
+                };
+                let blocks = car_read_all(&mut r, true)
+                    .await
+                    .map(|(blocks, _)| blocks)
+                    .map_err(|err| err.to_string());
+                assert_eq!(blocks, expected, "{}", car_filepath);
+            }
+        })
+    }
+
+    /// Not `Send`, shares its buffer through an `Rc`
+    struct RcReader {
+        buf: std::rc::Rc<Vec<u8>>,
+        pos: usize,
+    }
+
+    impl AsyncRead for RcReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let n = buf.len().min(self.buf.len() - self.pos);
+            buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    #[test]
+    fn decode_not_send_reader() {
+        executor::block_on(async {
+            let car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+            let (expected_blocks, _) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+
+            let r = RcReader {
+                buf: std::rc::Rc::new(car),
+                pos: 0,
+            };
+            let car_reader = CarReader::new(r, true).await.unwrap();
+            let blocks = car_reader
+                .map(|item| item.unwrap())
+                .collect::<Vec<_>>()
+                .await;
+
+            assert_eq!(blocks, expected_blocks);
+        })
+    }
+
+    #[test]
+    fn decode_cid_longer_than_section() {
+        executor::block_on(async {
+            // Empty roots header, then a section of len 4 with a 36 bytes CIDv1
+            let car = hex::decode(
+                "11a265726f6f7473806776657273696f6e01 04 01551220b6fbd675f98e2abd22d4ed29fdc83150fedc48597e92dd1a7a24381d44a27451"
+                    .replace(' ', ""),
+            )
+            .unwrap();
+            let err = car_read_all(&mut car.as_slice(), true).await.unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "invalid section header: CID runs past the section length 4 in section 0 at byte 18"
+            );
+        })
+    }
+
     async fn run_car_basic_test(car_filepath: &str, car_json_expected: &str) {
         let expected_car = std::fs::read_to_string(car_json_expected).unwrap();
         let expected_car: ExpectedCarv1 = serde_json::from_str(&expected_car).unwrap();
@@ -0,0 +1,610 @@
+use std::{collections::HashMap, fmt, marker::PhantomData, sync::Arc};
+
+use blake2b_simd::Params;
+use sha2::{Digest, Sha256};
+
+use crate::block_cid::{CODE_IDENTITY, CODE_SHA2_256};
+
+const CODE_BLAKE2B_256: u64 = 0xb220;
+#[cfg(feature = "sha2-512")]
+const CODE_SHA2_512: u64 = 0x13;
+#[cfg(feature = "sha3")]
+const CODE_SHA3_512: u64 = 0x14;
+#[cfg(feature = "sha3")]
+const CODE_SHA3_384: u64 = 0x15;
+#[cfg(feature = "sha3")]
+const CODE_SHA3_256: u64 = 0x16;
+#[cfg(feature = "sha3")]
+const CODE_SHA3_224: u64 = 0x17;
+#[cfg(feature = "keccak")]
+const CODE_KECCAK_224: u64 = 0x1a;
+#[cfg(feature = "keccak")]
+const CODE_KECCAK_256: u64 = 0x1b;
+#[cfg(feature = "keccak")]
+const CODE_KECCAK_384: u64 = 0x1c;
+#[cfg(feature = "keccak")]
+const CODE_KECCAK_512: u64 = 0x1d;
+#[cfg(feature = "blake3")]
+const CODE_BLAKE3: u64 = 0x1e;
+#[cfg(feature = "blake2s")]
+const CODE_BLAKE2S_256: u64 = 0xb260;
+
+/// Hash function of a multihash code, to verify that block digests match their CID.
+/// Register custom ones in a [`MultihashVerifierRegistry`].
+///
+/// # Examples
+/// ```
+/// use rs_car::{MultihashHasher, MultihashVerifier, MultihashVerifierRegistry};
+///
+/// /// Toy hash function, the digest is the block length
+/// struct LenVerifier;
+///
+/// struct LenHasher(u64);
+///
+/// impl MultihashVerifier for LenVerifier {
+///   fn name(&self) -> &str {
+///     "len"
+///   }
+///
+///   fn hasher(&self) -> Box<dyn MultihashHasher> {
+///     Box::new(LenHasher(0))
+///   }
+/// }
+///
+/// impl MultihashHasher for LenHasher {
+///   fn update(&mut self, data: &[u8]) {
+///     self.0 += data.len() as u64;
+///   }
+///
+///   fn finalize(self: Box<Self>) -> Vec<u8> {
+///     self.0.to_be_bytes().to_vec()
+///   }
+/// }
+///
+/// let mut verifiers = MultihashVerifierRegistry::default();
+/// verifiers.register(0x300000, LenVerifier);
+/// ```
+pub trait MultihashVerifier: Send + Sync {
+    /// Name of the hash function, for error messages
+    fn name(&self) -> &str;
+
+    /// Returns a hasher for a single block
+    fn hasher(&self) -> Box<dyn MultihashHasher>;
+
+    /// Returns a hasher for a single block whose CID digest is `digest_len` bytes long,
+    /// for hash functions with a variable digest length. Defaults to
+    /// [`MultihashVerifier::hasher()`].
+    fn hasher_with_len(&self, digest_len: usize) -> Box<dyn MultihashHasher> {
+        let _ = digest_len;
+        self.hasher()
+    }
+}
+
+/// Incremental digest of a single block. A block may be passed in chunks of any size to
+/// [`MultihashHasher::update()`].
+pub trait MultihashHasher: Send {
+    fn update(&mut self, data: &[u8]);
+
+    /// Returns the digest of all data passed to [`MultihashHasher::update()`]
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
+/// Hash functions by multihash code used to verify blocks. The default registry includes
+/// the built-in hash functions: identity, sha2-256 and blake2b-256. More are included
+/// with cargo features:
+///
+/// - `sha2-512`: sha2-512
+/// - `sha3`: sha3-224, sha3-256, sha3-384 and sha3-512
+/// - `keccak`: keccak-224, keccak-256, keccak-384 and keccak-512
+/// - `blake2s`: blake2s-256
+/// - `blake3`: blake3 with a digest of any length
+#[derive(Clone)]
+pub struct MultihashVerifierRegistry {
+    verifiers: HashMap<u64, Arc<dyn MultihashVerifier>>,
+}
+
+impl MultihashVerifierRegistry {
+    /// Returns a registry without any hash function, not even the built-in ones
+    pub fn empty() -> Self {
+        MultihashVerifierRegistry {
+            verifiers: HashMap::new(),
+        }
+    }
+
+    /// Registers the hash function of multihash `code`, replacing any previous one
+    pub fn register(&mut self, code: u64, verifier: impl MultihashVerifier + 'static) {
+        self.verifiers.insert(code, Arc::new(verifier));
+    }
+
+    /// Returns the hash function of multihash `code`, if registered
+    pub fn get(&self, code: u64) -> Option<&dyn MultihashVerifier> {
+        self.verifiers.get(&code).map(|verifier| verifier.as_ref())
+    }
+
+    /// Same as [`MultihashVerifierRegistry::get()`], to keep the hash function along with
+    /// a hasher of a streamed block
+    pub(crate) fn get_shared(&self, code: u64) -> Option<Arc<dyn MultihashVerifier>> {
+        self.verifiers.get(&code).cloned()
+    }
+}
+
+impl Default for MultihashVerifierRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(CODE_IDENTITY, IdentityVerifier);
+        registry.register(CODE_SHA2_256, DigestVerifier::<Sha256>::new("sha2-256"));
+        registry.register(CODE_BLAKE2B_256, Blake2b256Verifier);
+
+        #[cfg(feature = "sha2-512")]
+        registry.register(
+            CODE_SHA2_512,
+            DigestVerifier::<sha2::Sha512>::new("sha2-512"),
+        );
+
+        #[cfg(feature = "sha3")]
+        {
+            registry.register(
+                CODE_SHA3_224,
+                DigestVerifier::<sha3::Sha3_224>::new("sha3-224"),
+            );
+            registry.register(
+                CODE_SHA3_256,
+                DigestVerifier::<sha3::Sha3_256>::new("sha3-256"),
+            );
+            registry.register(
+                CODE_SHA3_384,
+                DigestVerifier::<sha3::Sha3_384>::new("sha3-384"),
+            );
+            registry.register(
+                CODE_SHA3_512,
+                DigestVerifier::<sha3::Sha3_512>::new("sha3-512"),
+            );
+        }
+
+        #[cfg(feature = "keccak")]
+        {
+            registry.register(
+                CODE_KECCAK_224,
+                DigestVerifier::<sha3::Keccak224>::new("keccak-224"),
+            );
+            registry.register(
+                CODE_KECCAK_256,
+                DigestVerifier::<sha3::Keccak256>::new("keccak-256"),
+            );
+            registry.register(
+                CODE_KECCAK_384,
+                DigestVerifier::<sha3::Keccak384>::new("keccak-384"),
+            );
+            registry.register(
+                CODE_KECCAK_512,
+                DigestVerifier::<sha3::Keccak512>::new("keccak-512"),
+            );
+        }
+
+        #[cfg(feature = "blake2s")]
+        registry.register(CODE_BLAKE2S_256, Blake2s256Verifier);
+
+        #[cfg(feature = "blake3")]
+        registry.register(CODE_BLAKE3, Blake3Verifier);
+
+        registry
+    }
+}
+
+impl fmt::Debug for MultihashVerifierRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut codes = self.verifiers.keys().collect::<Vec<_>>();
+        codes.sort();
+        f.debug_map()
+            .entries(
+                codes
+                    .into_iter()
+                    .map(|code| (code, self.verifiers[code].name())),
+            )
+            .finish()
+    }
+}
+
+/// The digest is the block data itself
+struct IdentityVerifier;
+
+/// Keeps at most one byte more than the CID digest, enough to tell that a longer block
+/// does not match it without copying the whole block
+struct IdentityHasher {
+    data: Vec<u8>,
+    max_len: usize,
+}
+
+impl MultihashVerifier for IdentityVerifier {
+    fn name(&self) -> &str {
+        "identity"
+    }
+
+    /// Without a digest length, the whole block is kept
+    fn hasher(&self) -> Box<dyn MultihashHasher> {
+        Box::new(IdentityHasher {
+            data: vec![],
+            max_len: usize::MAX,
+        })
+    }
+
+    fn hasher_with_len(&self, digest_len: usize) -> Box<dyn MultihashHasher> {
+        Box::new(IdentityHasher {
+            data: vec![],
+            max_len: digest_len + 1,
+        })
+    }
+}
+
+impl MultihashHasher for IdentityHasher {
+    fn update(&mut self, data: &[u8]) {
+        let len = data.len().min(self.max_len - self.data.len());
+        self.data.extend_from_slice(&data[..len]);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Hash function of a RustCrypto [`Digest`] type, as sha2 and sha3
+struct DigestVerifier<D> {
+    name: &'static str,
+    digest: PhantomData<fn() -> D>,
+}
+
+struct DigestHasher<D>(D);
+
+impl<D> DigestVerifier<D> {
+    fn new(name: &'static str) -> Self {
+        DigestVerifier {
+            name,
+            digest: PhantomData,
+        }
+    }
+}
+
+impl<D: Digest + Send + 'static> MultihashVerifier for DigestVerifier<D> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn hasher(&self) -> Box<dyn MultihashHasher> {
+        Box::new(DigestHasher(D::new()))
+    }
+}
+
+impl<D: Digest + Send> MultihashHasher for DigestHasher<D> {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().to_vec()
+    }
+}
+
+struct Blake2b256Verifier;
+
+struct Blake2b256Hasher(blake2b_simd::State);
+
+impl MultihashVerifier for Blake2b256Verifier {
+    fn name(&self) -> &str {
+        "blake2b-256"
+    }
+
+    fn hasher(&self) -> Box<dyn MultihashHasher> {
+        Box::new(Blake2b256Hasher(Params::new().hash_length(32).to_state()))
+    }
+}
+
+impl MultihashHasher for Blake2b256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().as_bytes().to_vec()
+    }
+}
+
+#[cfg(feature = "blake2s")]
+struct Blake2s256Verifier;
+
+#[cfg(feature = "blake2s")]
+struct Blake2s256Hasher(blake2s_simd::State);
+
+#[cfg(feature = "blake2s")]
+impl MultihashVerifier for Blake2s256Verifier {
+    fn name(&self) -> &str {
+        "blake2s-256"
+    }
+
+    fn hasher(&self) -> Box<dyn MultihashHasher> {
+        Box::new(Blake2s256Hasher(
+            blake2s_simd::Params::new().hash_length(32).to_state(),
+        ))
+    }
+}
+
+#[cfg(feature = "blake2s")]
+impl MultihashHasher for Blake2s256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().as_bytes().to_vec()
+    }
+}
+
+#[cfg(feature = "blake3")]
+struct Blake3Verifier;
+
+/// Extendable output of the length of the CID digest
+#[cfg(feature = "blake3")]
+struct Blake3Hasher {
+    hasher: blake3::Hasher,
+    digest_len: usize,
+}
+
+#[cfg(feature = "blake3")]
+impl MultihashVerifier for Blake3Verifier {
+    fn name(&self) -> &str {
+        "blake3"
+    }
+
+    fn hasher(&self) -> Box<dyn MultihashHasher> {
+        self.hasher_with_len(blake3::OUT_LEN)
+    }
+
+    fn hasher_with_len(&self, digest_len: usize) -> Box<dyn MultihashHasher> {
+        Box::new(Blake3Hasher {
+            hasher: blake3::Hasher::new(),
+            digest_len,
+        })
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl MultihashHasher for Blake3Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        let mut digest = vec![0; self.digest_len];
+        self.hasher.finalize_xof().fill(&mut digest);
+        digest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor;
+    use ipld_core::cid::multihash::Multihash;
+    use sha2::Sha512;
+
+    use super::*;
+    use crate::{
+        block_cid::assert_block_cid, CarDecodeError, CarReader, CarReaderOptions, CarWriter, Cid,
+    };
+
+    /// Private use multihash code, never built-in
+    const CODE_CUSTOM_SHA2_512: u64 = 0x300000;
+    const CODEC_RAW: u64 = 0x55;
+
+    struct Sha2_512Verifier;
+
+    struct Sha2_512Hasher(Sha512);
+
+    impl MultihashVerifier for Sha2_512Verifier {
+        fn name(&self) -> &str {
+            "sha2-512"
+        }
+
+        fn hasher(&self) -> Box<dyn MultihashHasher> {
+            Box::new(Sha2_512Hasher(Sha512::new()))
+        }
+    }
+
+    impl MultihashHasher for Sha2_512Hasher {
+        fn update(&mut self, data: &[u8]) {
+            self.0.update(data);
+        }
+
+        fn finalize(self: Box<Self>) -> Vec<u8> {
+            self.0.finalize().to_vec()
+        }
+    }
+
+    /// CARv1 with a single raw block, its CID multihash is `code` and `digest`
+    fn car_with_block(code: u64, digest: &[u8], block: &[u8]) -> Vec<u8> {
+        let cid = Cid::new_v1(CODEC_RAW, Multihash::wrap(code, digest).unwrap());
+        executor::block_on(async {
+            let mut car_writer = CarWriter::new(vec![], &[cid]).await.unwrap();
+            car_writer.write(&cid, block).await.unwrap();
+            car_writer.finish().await.unwrap()
+        })
+    }
+
+    fn read_blocks(car: &[u8], options: CarReaderOptions) -> Result<usize, CarDecodeError> {
+        executor::block_on(async {
+            let mut car_reader = CarReader::with_options(car, options).await?;
+            let mut count = 0;
+            while let Some(section) = car_reader.next_section().await {
+                section?;
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+
+    #[test]
+    fn registry_custom_verifier() {
+        let block = b"helloworld";
+        let car = car_with_block(CODE_CUSTOM_SHA2_512, &Sha512::digest(block), block);
+
+        // Not built-in
+        match read_blocks(&car, CarReaderOptions::default()) {
+            Err(CarDecodeError::UnsupportedHashCode { code, .. }) => {
+                assert_eq!(code, CODE_CUSTOM_SHA2_512)
+            }
+            x => panic!("other result {:?}", x),
+        }
+
+        let mut verifiers = MultihashVerifierRegistry::default();
+        verifiers.register(CODE_CUSTOM_SHA2_512, Sha2_512Verifier);
+        let options = CarReaderOptions {
+            verifiers,
+            ..Default::default()
+        };
+        assert_eq!(read_blocks(&car, options.clone()).unwrap(), 1);
+
+        // Custom verifiers are checked too
+        let car = car_with_block(CODE_CUSTOM_SHA2_512, &Sha512::digest(b"other"), block);
+        match read_blocks(&car, options) {
+            Err(CarDecodeError::BlockDigestMismatch { mismatch, .. }) => {
+                assert_eq!(mismatch.code, CODE_CUSTOM_SHA2_512);
+                assert_eq!(mismatch.hash, "sha2-512");
+                assert_eq!(mismatch.expected, Sha512::digest(b"other").to_vec());
+                assert_eq!(mismatch.actual, Sha512::digest(block).to_vec());
+            }
+            x => panic!("other result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn registry_empty_rejects_built_in() {
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let options = CarReaderOptions {
+            verifiers: MultihashVerifierRegistry::empty(),
+            ..Default::default()
+        };
+
+        match read_blocks(&car, options) {
+            Err(CarDecodeError::UnsupportedHashCode { .. }) => {}
+            x => panic!("other result {:?}", x),
+        }
+    }
+
+    #[test]
+    fn registry_default_built_in() {
+        let registry = MultihashVerifierRegistry::default();
+        assert!(format!("{:?}", registry).starts_with("{0: \"identity\", 18: \"sha2-256\""));
+        assert_eq!(registry.get(CODE_SHA2_256).unwrap().name(), "sha2-256");
+        assert_eq!(
+            registry.get(CODE_BLAKE2B_256).unwrap().name(),
+            "blake2b-256"
+        );
+        assert!(registry.get(CODE_CUSTOM_SHA2_512).is_none());
+    }
+
+    /// Verifies the digest of "abc" with the default registry, and rejects other data
+    fn assert_abc_digest(code: u64, digest_hex: &str) {
+        let registry = MultihashVerifierRegistry::default();
+        let digest = hex::decode(digest_hex).unwrap();
+        let cid = Cid::new_v1(CODEC_RAW, Multihash::wrap(code, &digest).unwrap());
+
+        assert_block_cid(&cid, b"abc", &registry).unwrap();
+        match assert_block_cid(&cid, b"abd", &registry) {
+            Err(CarDecodeError::BlockDigestMismatch { .. }) => {}
+            x => panic!("other result {:?}", x),
+        }
+
+        // Same result read by a CarReader
+        let car = car_with_block(code, &digest, b"abc");
+        assert_eq!(read_blocks(&car, CarReaderOptions::default()).unwrap(), 1);
+    }
+
+    #[test]
+    fn verify_built_in_vectors() {
+        assert_abc_digest(
+            CODE_SHA2_256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        );
+        assert_abc_digest(
+            CODE_BLAKE2B_256,
+            "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319",
+        );
+    }
+
+    #[cfg(feature = "sha2-512")]
+    #[test]
+    fn verify_sha2_512_vectors() {
+        assert_abc_digest(CODE_SHA2_512, "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f");
+    }
+
+    #[cfg(feature = "sha3")]
+    #[test]
+    fn verify_sha3_vectors() {
+        assert_abc_digest(
+            CODE_SHA3_224,
+            "e642824c3f8cf24ad09234ee7d3c766fc9a3a5168d0c94ad73b46fdf",
+        );
+        assert_abc_digest(
+            CODE_SHA3_256,
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532",
+        );
+        assert_abc_digest(CODE_SHA3_384, "ec01498288516fc926459f58e2c6ad8df9b473cb0fc08c2596da7cf0e49be4b298d88cea927ac7f539f1edf228376d25");
+        assert_abc_digest(CODE_SHA3_512, "b751850b1a57168a5693cd924b6b096e08f621827444f70d884f5d0240d2712e10e116e9192af3c91a7ec57647e3934057340b4cf408d5a56592f8274eec53f0");
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn verify_keccak_vectors() {
+        assert_abc_digest(
+            CODE_KECCAK_224,
+            "c30411768506ebe1c2871b1ee2e87d38df342317300a9b97a95ec6a8",
+        );
+        assert_abc_digest(
+            CODE_KECCAK_256,
+            "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+        );
+        assert_abc_digest(CODE_KECCAK_384, "f7df1165f033337be098e7d288ad6a2f74409d7a60b49c36642218de161b1f99f8c681e4afaf31a34db29fb763e3c28e");
+        assert_abc_digest(CODE_KECCAK_512, "18587dc2ea106b9a1563e32b3312421ca164c7f1f07bc922a9c83d77cea3a1e5d0c69910739025372dc14ac9642629379540c17e2a65b19d77aa511a9d00bb96");
+    }
+
+    #[cfg(feature = "blake2s")]
+    #[test]
+    fn verify_blake2s_vectors() {
+        assert_abc_digest(
+            CODE_BLAKE2S_256,
+            "508c5e8c327c14e2e1a72ba34eeb452f37458b209ed63a294d999b4c86675982",
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn verify_blake3_vectors() {
+        assert_abc_digest(
+            CODE_BLAKE3,
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+        );
+        // Extendable output, shorter and longer than the default 32 bytes
+        assert_abc_digest(CODE_BLAKE3, "6437b3ac38465133ffb63b75273a8db5");
+        assert_abc_digest(CODE_BLAKE3, "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d851fb250ae7393f5d02813b65d521a0d492d9ba09cf7ce7f4cffd900f23374bf0b");
+    }
+
+    #[test]
+    fn verify_identity_keeps_digest_len() {
+        let registry = MultihashVerifierRegistry::default();
+        let cid = Cid::new_v1(CODEC_RAW, Multihash::wrap(CODE_IDENTITY, b"abc").unwrap());
+        assert_block_cid(&cid, b"abc", &registry).unwrap();
+
+        // Only the bytes past the digest length needed to tell the mismatch are kept
+        let block = vec![b'a'; 1 << 20];
+        match assert_block_cid(&cid, &block, &registry) {
+            Err(CarDecodeError::BlockDigestMismatch { mismatch, .. }) => {
+                assert_eq!(mismatch.actual, b"aaaa")
+            }
+            x => panic!("other result {:?}", x),
+        }
+        match assert_block_cid(&cid, b"ab", &registry) {
+            Err(CarDecodeError::BlockDigestMismatch { mismatch, .. }) => {
+                assert_eq!(mismatch.actual, b"ab")
+            }
+            x => panic!("other result {:?}", x),
+        }
+    }
+}
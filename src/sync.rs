@@ -0,0 +1,259 @@
+//! Blocking API over [`std::io::Read`], same as the async one at the crate root.
+//!
+//! - To get a block iterator [`CarReader::new()`]
+//! - To stream big blocks without buffering them [`CarReader::next_block_reader()`]
+//! - To read all blocks in memory [car_read_all]
+
+use std::{
+    future::Future,
+    io::{self, Read},
+    pin::Pin,
+};
+
+use futures::{future::poll_fn, io::AllowStdIo, AsyncRead, FutureExt};
+
+use crate::{
+    car_header::{read_car_header, CarHeader},
+    car_index::CarIndex,
+    car_reader_options::CarReaderOptions,
+    car_section::{CarSection, SectionStream},
+    error::CarDecodeError,
+    Cid,
+};
+
+/// Decodes a CAR stream yielding its blocks and optionally verifying integrity.
+/// Supports CARv1 and CARv2 formats. Blocking version of [`crate::CarReader`].
+///
+/// - To get a block iterator [`CarReader::new()`]
+/// - To read all blocks in memory [car_read_all]
+pub struct CarReader<R> {
+    r: AllowStdIo<R>,
+    pub header: CarHeader,
+    sections: SectionStream,
+}
+
+impl<R> CarReader<R>
+where
+    R: Read,
+{
+    /// Decodes a CAR stream up to the header. Returns an `Iterator` type that yields
+    /// blocks. The CAR header is available in [`CarReader::header`].
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::sync::CarReader;
+    ///
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let r = std::fs::File::open("./tests/custom_fixtures/helloworld.car")?;
+    ///
+    ///   let car_reader = CarReader::new(r, true)?;
+    ///   println!("{:?}", car_reader.header);
+    ///
+    ///   for item in car_reader {
+    ///     let (cid, block) = item?;
+    ///     println!("{:?} {} bytes", cid, block.len());
+    ///   }
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn new(r: R, validate_block_hash: bool) -> Result<CarReader<R>, CarDecodeError> {
+        Self::with_options(
+            r,
+            CarReaderOptions {
+                validate_block_hash,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as [`CarReader::new()`] with custom limits and checks
+    pub fn with_options(r: R, options: CarReaderOptions) -> Result<CarReader<R>, CarDecodeError> {
+        let mut r = AllowStdIo::new(r);
+        let header = poll_std_io(read_car_header(&mut r, &options))?;
+        Ok(CarReader {
+            r,
+            sections: SectionStream::new(&header, options),
+            header,
+        })
+    }
+
+    /// Returns the next block with the location of its section in the CAR stream.
+    /// Blocking version of [`crate::CarReader::next_section()`].
+    pub fn next_section(&mut self) -> Option<Result<CarSection, CarDecodeError>> {
+        poll_std_io(poll_fn(|cx| {
+            self.sections.poll_next_section(cx, &mut self.r)
+        }))
+    }
+
+    /// Returns the next block as a [`std::io::Read`] instead of buffering it in memory.
+    /// Blocking version of [`crate::CarReader::next_block_reader()`].
+    ///
+    /// # Examples
+    /// ```
+    /// use rs_car::sync::CarReader;
+    ///
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let r = std::fs::File::open("./tests/custom_fixtures/helloworld.car")?;
+    ///
+    ///   let mut car_reader = CarReader::new(r, true)?;
+    ///
+    ///   while let Some(block_reader) = car_reader.next_block_reader() {
+    ///     let mut block_reader = block_reader?;
+    ///     println!("{:?} {} bytes", block_reader.cid, block_reader.block_len);
+    ///     std::io::copy(&mut block_reader, &mut std::io::sink())?;
+    ///   }
+    ///
+    ///   Ok(())
+    /// }
+    /// ```
+    pub fn next_block_reader(&mut self) -> Option<Result<CarBlockReader<'_, R>, CarDecodeError>> {
+        poll_std_io(self.sections.next_block_reader(&mut self.r)).map(|block_reader| {
+            block_reader.map(|inner| CarBlockReader {
+                cid: inner.cid,
+                offset: inner.offset,
+                block_len: inner.block_len,
+                inner,
+            })
+        })
+    }
+
+    /// Reads the trailing index of a CARv2 in the same pass.
+    /// Blocking version of [`crate::CarReader::read_index()`].
+    pub fn read_index(&mut self) -> Result<Option<CarIndex>, CarDecodeError> {
+        poll_std_io(self.sections.read_index(&mut self.r))
+    }
+
+    /// Returns the underlying reader
+    pub fn into_inner(self) -> R {
+        self.r.into_inner()
+    }
+}
+
+impl<R> Iterator for CarReader<R>
+where
+    R: Read,
+{
+    type Item = Result<(Cid, Vec<u8>), CarDecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_section()
+            .map(|section| section.map(|s| (s.cid, s.block)))
+    }
+}
+
+/// A block streamed from its section in the CAR stream, read with [`std::io::Read`].
+/// Blocking version of [`crate::CarBlockReader`].
+pub struct CarBlockReader<'a, R> {
+    pub cid: Cid,
+    /// Absolute byte offset of the section varint, from the start of the CAR stream
+    pub offset: u64,
+    /// Byte length of the block data
+    pub block_len: usize,
+    inner: crate::CarBlockReader<'a, AllowStdIo<R>>,
+}
+
+impl<R> Read for CarBlockReader<'_, R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_std_io(poll_fn(|cx| Pin::new(&mut self.inner).poll_read(cx, buf)))
+    }
+}
+
+/// Decodes a CAR stream buffering all blocks in memory. For an Iterator API use [CarReader].
+/// Blocking version of [`crate::car_read_all`].
+///
+/// # Examples
+///
+/// ```
+/// use rs_car::sync::car_read_all;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///   let mut r = std::fs::File::open("./tests/custom_fixtures/helloworld.car")?;
+///
+///   let (blocks, header) = car_read_all(&mut r, true)?;
+///   println!("{:?}", header);
+///
+///   for (cid, block) in blocks {
+///     println!("{:?} {} bytes", cid, block.len());
+///   }
+///
+///   Ok(())
+/// }
+/// ```
+#[allow(clippy::type_complexity)]
+pub fn car_read_all<R: Read>(
+    r: &mut R,
+    validate_block_hash: bool,
+) -> Result<(Vec<(Cid, Vec<u8>)>, CarHeader), CarDecodeError> {
+    let mut decoder = CarReader::new(r, validate_block_hash)?;
+    let items = decoder.by_ref().collect::<Result<Vec<_>, _>>()?;
+
+    Ok((items, decoder.header))
+}
+
+/// Runs a future of the async decoding functions to completion. Reads from [`AllowStdIo`]
+/// block the current thread and never return `Poll::Pending`, so a single poll is enough.
+fn poll_std_io<F: Future>(future: F) -> F::Output {
+    future
+        .now_or_never()
+        .expect("reads from AllowStdIo are never pending")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn sync_decode_carv1_helloworld() {
+        let mut file = std::fs::File::open("./tests/custom_fixtures/helloworld.car").unwrap();
+        let (blocks, header) = car_read_all(&mut file, true).unwrap();
+
+        let root_cid = Cid::from_str("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf").unwrap();
+        let root_block = hex::decode("0a110802120b68656c6c6f776f726c640a180b").unwrap();
+
+        assert_eq!(blocks, vec!((root_cid, root_block)));
+        assert_eq!(header.roots, vec!(root_cid));
+    }
+
+    #[test]
+    fn sync_same_as_async() {
+        for car_filepath in [
+            "./tests/spec_fixtures/carv1-basic.car",
+            "./tests/spec_fixtures/carv2-basic.car",
+            "./tests/go_car_fixtures/sample-v1.car",
+            "./tests/go_car_fixtures/sample-wrapped-v2.car",
+            "./tests/go_car_fixtures/sample-rw-bs-v2.car",
+        ] {
+            let car = std::fs::read(car_filepath).unwrap();
+            let (blocks, header) = car_read_all(&mut car.as_slice(), true).unwrap();
+            let (blocks_async, header_async) =
+                futures::executor::block_on(crate::car_read_all(&mut car.as_slice(), true))
+                    .unwrap();
+
+            assert_eq!(blocks, blocks_async, "{}", car_filepath);
+            assert_eq!(header.roots, header_async.roots, "{}", car_filepath);
+        }
+    }
+
+    #[test]
+    fn sync_stops_after_error() {
+        let car =
+            std::fs::read("./tests/go_car_fixtures/sample-v1-with-zero-len-section.car").unwrap();
+        let items = CarReader::new(car.as_slice(), true)
+            .unwrap()
+            .collect::<Vec<_>>();
+
+        match items.last() {
+            Some(Err(err)) => assert_eq!(
+                err.to_string(),
+                "invalid section header: zero length in section 1049 at byte 479907"
+            ),
+            x => panic!("other result {:?}", x),
+        }
+    }
+}
@@ -0,0 +1,661 @@
+//! Native [`tokio::io`] API, same as the async one at the crate root. Requires the `tokio` feature.
+//!
+//! - To get a block streamer [`CarReader::new()`]
+//! - To stream big blocks without buffering them [`CarReader::next_block_reader()`]
+//! - To read all blocks in memory [car_read_all]
+//! - To get blocks by CID from an indexed CARv2 [`CarIndexedReader::new()`]
+//! - To build a CARv2 index of an indexless CAR [generate_index]
+//! - To convert a CARv1 into an indexed CARv2 [wrap_v1], and back [extract_v1]
+//! - To write a CARv1 stream [`CarWriter::new()`]
+//! - To write a CARv2 stream [`CarV2Writer::new()`] or [`CarV2BufferedWriter::new()`]
+
+use std::{
+    collections::BTreeMap,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{future::poll_fn, Stream, StreamExt};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use tokio_util::compat::{
+    Compat, FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt,
+};
+
+use crate::{
+    car_header::{read_car_header, CarHeader},
+    car_index::{CarIndex, CarIndexCodec},
+    car_section::{CarSection, SectionStream},
+    error::{CarConvertError, CarDecodeError, CarEncodeError},
+    CarReaderOptions, CarV2WriterOptions, Cid, Ipld,
+};
+
+/// Decodes a CAR stream yielding its blocks and optionally verifying integrity.
+/// Supports CARv1 and CARv2 formats. Tokio version of [`crate::CarReader`].
+///
+/// - To get a block streamer [`CarReader::new()`]
+/// - To read all blocks in memory [car_read_all]
+pub struct CarReader<R> {
+    r: Compat<R>,
+    pub header: CarHeader,
+    sections: SectionStream,
+}
+
+impl<R> CarReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Decodes a CAR stream up to the header. Tokio version of [`crate::CarReader::new()`].
+    pub async fn new(r: R, validate_block_hash: bool) -> Result<CarReader<R>, CarDecodeError> {
+        Self::with_options(
+            r,
+            CarReaderOptions {
+                validate_block_hash,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Same as [`CarReader::new()`] with custom limits and checks
+    pub async fn with_options(
+        r: R,
+        options: CarReaderOptions,
+    ) -> Result<CarReader<R>, CarDecodeError> {
+        let mut r = r.compat();
+        let header = read_car_header(&mut r, &options).await?;
+        Ok(CarReader {
+            r,
+            sections: SectionStream::new(&header, options),
+            header,
+        })
+    }
+
+    /// Returns the next block with the location of its section in the CAR stream.
+    /// Tokio version of [`crate::CarReader::next_section()`].
+    pub async fn next_section(&mut self) -> Option<Result<CarSection, CarDecodeError>> {
+        poll_fn(|cx| self.poll_next_section(cx)).await
+    }
+
+    /// Polling version of [`CarReader::next_section()`]
+    pub fn poll_next_section(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<CarSection, CarDecodeError>>> {
+        self.sections.poll_next_section(cx, &mut self.r)
+    }
+
+    /// Returns the next block as a [`tokio::io::AsyncRead`].
+    /// Tokio version of [`crate::CarReader::next_block_reader()`].
+    pub async fn next_block_reader(
+        &mut self,
+    ) -> Option<Result<CarBlockReader<'_, R>, CarDecodeError>> {
+        self.sections
+            .next_block_reader(&mut self.r)
+            .await
+            .map(|block_reader| {
+                block_reader.map(|inner| CarBlockReader {
+                    cid: inner.cid,
+                    offset: inner.offset,
+                    block_len: inner.block_len,
+                    inner: inner.compat(),
+                })
+            })
+    }
+
+    /// Reads the trailing index of a CARv2 in the same pass.
+    /// Tokio version of [`crate::CarReader::read_index()`].
+    pub async fn read_index(&mut self) -> Result<Option<CarIndex>, CarDecodeError> {
+        self.sections.read_index(&mut self.r).await
+    }
+
+    /// Returns the underlying reader
+    pub fn into_inner(self) -> R {
+        self.r.into_inner()
+    }
+}
+
+/// A block streamed from its section in the CAR stream, read with
+/// [`tokio::io::AsyncRead`]. Tokio version of [`crate::CarBlockReader`].
+pub struct CarBlockReader<'a, R> {
+    pub cid: Cid,
+    /// Absolute byte offset of the section varint, from the start of the CAR stream
+    pub offset: u64,
+    /// Byte length of the block data
+    pub block_len: usize,
+    inner: Compat<crate::CarBlockReader<'a, Compat<R>>>,
+}
+
+impl<R> AsyncRead for CarBlockReader<'_, R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+/// Random-access reader of an indexed CARv2 stream. Tokio version of
+/// [`crate::CarIndexedReader`].
+///
+/// - To open an indexed CARv2 [`CarIndexedReader::new()`]
+pub struct CarIndexedReader<R> {
+    inner: crate::CarIndexedReader<Compat<R>>,
+}
+
+impl<R> CarIndexedReader<R>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    /// Decodes a CARv2 header and its index. Tokio version of
+    /// [`crate::CarIndexedReader::new()`].
+    pub async fn new(
+        r: R,
+        validate_block_hash: bool,
+    ) -> Result<CarIndexedReader<R>, CarDecodeError> {
+        Self::with_options(
+            r,
+            CarReaderOptions {
+                validate_block_hash,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Same as [`CarIndexedReader::new()`] with custom limits and checks
+    pub async fn with_options(
+        r: R,
+        options: CarReaderOptions,
+    ) -> Result<CarIndexedReader<R>, CarDecodeError> {
+        Ok(CarIndexedReader {
+            inner: crate::CarIndexedReader::with_options(r.compat(), options).await?,
+        })
+    }
+
+    /// CAR header of the stream
+    pub fn header(&self) -> &CarHeader {
+        &self.inner.header
+    }
+
+    /// Returns the block data of `cid`, or `None` if not in the index.
+    /// Tokio version of [`crate::CarIndexedReader::get()`].
+    pub async fn get(&mut self, cid: &Cid) -> Result<Option<Vec<u8>>, CarDecodeError> {
+        self.inner.get(cid).await
+    }
+
+    /// Returns true if the index includes a block with the multihash of `cid`.
+    /// Tokio version of [`crate::CarIndexedReader::has()`].
+    pub async fn has(&mut self, cid: &Cid) -> Result<bool, CarDecodeError> {
+        self.inner.has(cid).await
+    }
+
+    /// Returns the byte length of the block data of `cid`, or `None` if not in the index.
+    /// Tokio version of [`crate::CarIndexedReader::get_size()`].
+    pub async fn get_size(&mut self, cid: &Cid) -> Result<Option<usize>, CarDecodeError> {
+        self.inner.get_size(cid).await
+    }
+
+    /// Index of the CARv2 stream
+    pub fn index(&self) -> &CarIndex {
+        self.inner.index()
+    }
+
+    /// Returns the underlying reader
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().into_inner()
+    }
+}
+
+/// Decodes a CAR stream buffering all blocks in memory. Tokio version of
+/// [`crate::car_read_all`].
+pub async fn car_read_all<R: AsyncRead + Unpin>(
+    r: &mut R,
+    validate_block_hash: bool,
+) -> Result<(Vec<(Cid, Vec<u8>)>, CarHeader), CarDecodeError> {
+    let mut decoder = CarReader::new(r, validate_block_hash).await?;
+    let mut items: Vec<(Cid, Vec<u8>)> = vec![];
+
+    while let Some(item) = decoder.next().await {
+        let item = item?;
+        items.push(item);
+    }
+
+    Ok((items, decoder.header))
+}
+
+/// Builds a CARv2 index of a CAR stream. Tokio version of [`crate::generate_index()`].
+pub async fn generate_index<R: AsyncRead + Unpin>(
+    r: &mut R,
+    codec: CarIndexCodec,
+) -> Result<CarIndex, CarDecodeError> {
+    crate::generate_index(&mut r.compat(), codec).await
+}
+
+/// Same as [`generate_index()`] with custom limits and checks. Tokio version of
+/// [`crate::generate_index_with_options()`].
+pub async fn generate_index_with_options<R: AsyncRead + Unpin>(
+    r: &mut R,
+    codec: CarIndexCodec,
+    options: CarReaderOptions,
+) -> Result<CarIndex, CarDecodeError> {
+    crate::generate_index_with_options(&mut r.compat(), codec, options).await
+}
+
+/// Converts a CAR stream into an indexed CARv2 stream. Tokio version of
+/// [`crate::wrap_v1()`].
+pub async fn wrap_v1<R, W>(
+    r: &mut R,
+    w: &mut W,
+    options: CarV2WriterOptions,
+) -> Result<(), CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + AsyncSeek + Unpin,
+{
+    crate::wrap_v1(&mut r.compat(), &mut w.compat_write(), options).await
+}
+
+/// Same as [`wrap_v1()`] with custom limits and checks to read `r`. Tokio version of
+/// [`crate::wrap_v1_with_options()`].
+pub async fn wrap_v1_with_options<R, W>(
+    r: &mut R,
+    w: &mut W,
+    options: CarV2WriterOptions,
+    reader_options: CarReaderOptions,
+) -> Result<(), CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + AsyncSeek + Unpin,
+{
+    crate::wrap_v1_with_options(
+        &mut r.compat(),
+        &mut w.compat_write(),
+        options,
+        reader_options,
+    )
+    .await
+}
+
+/// Extracts the inner CARv1 data payload of a CAR stream. Tokio version of
+/// [`crate::extract_v1()`].
+pub async fn extract_v1<R, W>(r: &mut R, w: &mut W) -> Result<(), CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    crate::extract_v1(&mut r.compat(), &mut w.compat_write()).await
+}
+
+/// Same as [`extract_v1()`] with custom limits and checks of the CAR header. Tokio version
+/// of [`crate::extract_v1_with_options()`].
+pub async fn extract_v1_with_options<R, W>(
+    r: &mut R,
+    w: &mut W,
+    options: CarReaderOptions,
+) -> Result<(), CarConvertError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    crate::extract_v1_with_options(&mut r.compat(), &mut w.compat_write(), options).await
+}
+
+impl<R> Stream for CarReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    type Item = Result<(Cid, Vec<u8>), CarDecodeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::into_inner(self)
+            .poll_next_section(cx)
+            .map(|item| item.map(|section| section.map(|s| (s.cid, s.block))))
+    }
+}
+
+/// Encodes a CARv1 stream writing its header and then blocks one by one.
+/// Tokio version of [`crate::CarWriter`].
+pub struct CarWriter<W> {
+    inner: crate::CarWriter<Compat<W>>,
+}
+
+impl<W> CarWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// Writes the CARv1 header with `roots` to `w`. Tokio version of
+    /// [`crate::CarWriter::new()`].
+    pub async fn new(w: W, roots: &[Cid]) -> Result<CarWriter<W>, CarEncodeError> {
+        Ok(CarWriter {
+            inner: crate::CarWriter::new(w.compat_write(), roots).await?,
+        })
+    }
+
+    /// Same as [`CarWriter::new()`] with `extra` keys in the CARv1 header, see
+    /// [`crate::CarWriter::with_extra()`]
+    pub async fn with_extra(
+        w: W,
+        roots: &[Cid],
+        extra: &BTreeMap<String, Ipld>,
+    ) -> Result<CarWriter<W>, CarEncodeError> {
+        Ok(CarWriter {
+            inner: crate::CarWriter::with_extra(w.compat_write(), roots, extra).await?,
+        })
+    }
+
+    /// Appends a block section `[varint|CID|block]`. The block is not checked
+    /// against the CID's multihash.
+    pub async fn write(&mut self, cid: &Cid, block: &[u8]) -> Result<(), CarEncodeError> {
+        self.inner.write(cid, block).await
+    }
+
+    /// Total byte length written so far, including the header
+    pub fn written_bytes(&self) -> usize {
+        self.inner.written_bytes()
+    }
+
+    /// Flushes the underlying writer and returns it
+    pub async fn finish(self) -> Result<W, CarEncodeError> {
+        Ok(self.inner.finish().await?.into_inner())
+    }
+}
+
+/// Encodes a CARv2 stream to a seekable output. Tokio version of [`crate::CarV2Writer`].
+pub struct CarV2Writer<W> {
+    inner: crate::CarV2Writer<Compat<W>>,
+}
+
+impl<W> CarV2Writer<W>
+where
+    W: AsyncWrite + AsyncSeek + Unpin,
+{
+    /// Writes the CARv2 pragma, a placeholder header and the inner CARv1 header
+    /// with `roots`. Tokio version of [`crate::CarV2Writer::new()`].
+    pub async fn new(w: W, roots: &[Cid]) -> Result<CarV2Writer<W>, CarEncodeError> {
+        Self::with_options(w, roots, CarV2WriterOptions::default()).await
+    }
+
+    /// Same as [`CarV2Writer::new()`] with custom layout options
+    pub async fn with_options(
+        w: W,
+        roots: &[Cid],
+        options: CarV2WriterOptions,
+    ) -> Result<CarV2Writer<W>, CarEncodeError> {
+        Ok(CarV2Writer {
+            inner: crate::CarV2Writer::with_options(w.compat_write(), roots, options).await?,
+        })
+    }
+
+    /// Same as [`CarV2Writer::with_options()`] with `extra` keys in the inner CARv1
+    /// header, see [`crate::CarWriter::with_extra()`]
+    pub async fn with_extra(
+        w: W,
+        roots: &[Cid],
+        extra: &BTreeMap<String, Ipld>,
+        options: CarV2WriterOptions,
+    ) -> Result<CarV2Writer<W>, CarEncodeError> {
+        Ok(CarV2Writer {
+            inner: crate::CarV2Writer::with_extra(w.compat_write(), roots, extra, options).await?,
+        })
+    }
+
+    /// Appends a block section `[varint|CID|block]` to the CARv1 data payload.
+    /// The block is not checked against the CID's multihash.
+    pub async fn write(&mut self, cid: &Cid, block: &[u8]) -> Result<(), CarEncodeError> {
+        self.inner.write(cid, block).await
+    }
+
+    /// Writes the index, fills in the CARv2 header, and returns the output
+    /// positioned at the end of the CARv2 stream.
+    pub async fn finish(self) -> Result<W, CarEncodeError> {
+        Ok(self.inner.finish().await?.into_inner())
+    }
+}
+
+/// Encodes a CARv2 stream to a non-seekable output, buffering the CARv1 data payload
+/// in memory. Tokio version of [`crate::CarV2BufferedWriter`].
+pub struct CarV2BufferedWriter<W> {
+    inner: crate::CarV2BufferedWriter<Compat<W>>,
+}
+
+impl<W> CarV2BufferedWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// Returns a writer to append blocks with [`CarV2BufferedWriter::write()`].
+    /// Nothing is written to `w` until [`CarV2BufferedWriter::finish()`].
+    pub async fn new(w: W, roots: &[Cid]) -> Result<CarV2BufferedWriter<W>, CarEncodeError> {
+        Self::with_options(w, roots, CarV2WriterOptions::default()).await
+    }
+
+    /// Same as [`CarV2BufferedWriter::new()`] with custom layout options
+    pub async fn with_options(
+        w: W,
+        roots: &[Cid],
+        options: CarV2WriterOptions,
+    ) -> Result<CarV2BufferedWriter<W>, CarEncodeError> {
+        Ok(CarV2BufferedWriter {
+            inner: crate::CarV2BufferedWriter::with_options(w.compat_write(), roots, options)
+                .await?,
+        })
+    }
+
+    /// Same as [`CarV2BufferedWriter::with_options()`] with `extra` keys in the inner
+    /// CARv1 header, see [`crate::CarWriter::with_extra()`]
+    pub async fn with_extra(
+        w: W,
+        roots: &[Cid],
+        extra: &BTreeMap<String, Ipld>,
+        options: CarV2WriterOptions,
+    ) -> Result<CarV2BufferedWriter<W>, CarEncodeError> {
+        Ok(CarV2BufferedWriter {
+            inner: crate::CarV2BufferedWriter::with_extra(w.compat_write(), roots, extra, options)
+                .await?,
+        })
+    }
+
+    /// Appends a block section `[varint|CID|block]` to the buffered CARv1 data payload.
+    /// The block is not checked against the CID's multihash.
+    pub async fn write(&mut self, cid: &Cid, block: &[u8]) -> Result<(), CarEncodeError> {
+        self.inner.write(cid, block).await
+    }
+
+    /// Writes the full CARv2 stream and returns the output
+    pub async fn finish(self) -> Result<W, CarEncodeError> {
+        Ok(self.inner.finish().await?.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, str::FromStr};
+
+    use super::*;
+    use crate::car_header::CarVersion;
+
+    #[tokio::test]
+    async fn tokio_decode_carv1_helloworld() {
+        let mut file = tokio::fs::File::open("./tests/custom_fixtures/helloworld.car")
+            .await
+            .unwrap();
+        let (blocks, header) = car_read_all(&mut file, true).await.unwrap();
+
+        let root_cid = Cid::from_str("QmUU2HcUBVSXkfWPUc3WUSeCMrWWeEJTuAgR9uyWBhh9Nf").unwrap();
+        let root_block = hex::decode("0a110802120b68656c6c6f776f726c640a180b").unwrap();
+
+        assert_eq!(blocks, vec!((root_cid, root_block)));
+        assert_eq!(header.version, CarVersion::V1);
+        assert_eq!(header.roots, vec!(root_cid));
+    }
+
+    #[tokio::test]
+    async fn tokio_same_as_async_std() {
+        for car_filepath in [
+            "./tests/spec_fixtures/carv1-basic.car",
+            "./tests/spec_fixtures/carv2-basic.car",
+            "./tests/go_car_fixtures/sample-v1.car",
+            "./tests/go_car_fixtures/sample-wrapped-v2.car",
+            "./tests/go_car_fixtures/sample-rw-bs-v2.car",
+        ] {
+            let mut file = tokio::fs::File::open(car_filepath).await.unwrap();
+            let (blocks, header) = car_read_all(&mut file, true).await.unwrap();
+
+            let mut file = async_std::fs::File::open(car_filepath).await.unwrap();
+            let (blocks_async_std, header_async_std) =
+                crate::car_read_all(&mut file, true).await.unwrap();
+
+            assert_eq!(blocks, blocks_async_std, "{}", car_filepath);
+            assert_eq!(header.roots, header_async_std.roots, "{}", car_filepath);
+        }
+    }
+
+    #[tokio::test]
+    async fn tokio_stops_after_error() {
+        let car =
+            std::fs::read("./tests/go_car_fixtures/sample-v1-with-zero-len-section.car").unwrap();
+        let items = CarReader::new(&mut car.as_slice(), true)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        match items.last() {
+            Some(Err(err)) => assert_eq!(
+                err.to_string(),
+                "invalid section header: zero length in section 1049 at byte 479907"
+            ),
+            x => panic!("other result {:?}", x),
+        }
+    }
+
+    #[tokio::test]
+    async fn tokio_writers_same_as_async_std() {
+        let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+        let (blocks, header) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+
+        let mut car_writer = CarWriter::new(Vec::new(), &header.roots).await.unwrap();
+        let mut car_v2_writer = CarV2Writer::new(Cursor::new(Vec::new()), &header.roots)
+            .await
+            .unwrap();
+        let mut car_v2_buffered_writer = CarV2BufferedWriter::new(Vec::new(), &header.roots)
+            .await
+            .unwrap();
+        for (cid, block) in &blocks {
+            car_writer.write(cid, block).await.unwrap();
+            car_v2_writer.write(cid, block).await.unwrap();
+            car_v2_buffered_writer.write(cid, block).await.unwrap();
+        }
+        assert_eq!(car_writer.written_bytes(), car.len());
+        let car_v1 = car_writer.finish().await.unwrap();
+        let car_v2 = car_v2_writer.finish().await.unwrap().into_inner();
+        let car_v2_buffered = car_v2_buffered_writer.finish().await.unwrap();
+
+        let mut async_std_writer =
+            crate::CarV2Writer::new(futures::io::Cursor::new(Vec::new()), &header.roots)
+                .await
+                .unwrap();
+        for (cid, block) in &blocks {
+            async_std_writer.write(cid, block).await.unwrap();
+        }
+        let car_v2_async_std = async_std_writer.finish().await.unwrap().into_inner();
+
+        assert_eq!(hex::encode(car_v1), hex::encode(&car));
+        assert_eq!(hex::encode(&car_v2), hex::encode(&car_v2_async_std));
+        assert_eq!(hex::encode(car_v2_buffered), hex::encode(&car_v2_async_std));
+    }
+
+    #[tokio::test]
+    async fn tokio_next_block_reader_same_as_async() {
+        let car = std::fs::read("./tests/go_car_fixtures/sample-wrapped-v2.car").unwrap();
+        let (expected_blocks, _) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+
+        let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+        let mut blocks = vec![];
+        while let Some(block_reader) = car_reader.next_block_reader().await {
+            let mut block_reader = block_reader.unwrap();
+            let mut block = vec![];
+            tokio::io::AsyncReadExt::read_to_end(&mut block_reader, &mut block)
+                .await
+                .unwrap();
+            assert_eq!(block.len(), block_reader.block_len);
+            blocks.push((block_reader.cid, block));
+        }
+        assert_eq!(blocks, expected_blocks);
+
+        // The index is still read after streaming all blocks
+        assert!(car_reader.read_index().await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn tokio_indexed_reader_same_as_async() {
+        let car_filepath = "./tests/go_car_fixtures/sample-wrapped-v2.car";
+        let car = std::fs::read(car_filepath).unwrap();
+        let (blocks, _) = car_read_all(&mut car.as_slice(), true).await.unwrap();
+
+        let file = tokio::fs::File::open(car_filepath).await.unwrap();
+        let mut car_reader = CarIndexedReader::new(file, true).await.unwrap();
+        let async_std_reader =
+            crate::CarIndexedReader::new(futures::io::Cursor::new(car.clone()), true)
+                .await
+                .unwrap();
+        assert_eq!(car_reader.header().roots, async_std_reader.header.roots);
+        assert_eq!(car_reader.index(), async_std_reader.index());
+
+        for (cid, block) in blocks {
+            assert!(car_reader.has(&cid).await.unwrap());
+            assert_eq!(car_reader.get_size(&cid).await.unwrap(), Some(block.len()));
+            assert_eq!(car_reader.get(&cid).await.unwrap(), Some(block));
+        }
+    }
+
+    #[tokio::test]
+    async fn tokio_read_index_same_as_async() {
+        let car = std::fs::read("./tests/go_car_fixtures/sample-wrapped-v2.car").unwrap();
+
+        let mut car_reader = CarReader::new(car.as_slice(), true).await.unwrap();
+        let index = car_reader.read_index().await.unwrap();
+
+        let mut async_std_reader = crate::CarReader::new(car.as_slice(), true).await.unwrap();
+        let index_async_std = async_std_reader.read_index().await.unwrap();
+
+        assert!(index.is_some());
+        assert_eq!(index, index_async_std);
+    }
+
+    #[tokio::test]
+    async fn tokio_convert_same_as_async() {
+        let car = std::fs::read("./tests/go_car_fixtures/sample-v1.car").unwrap();
+
+        let index = generate_index(&mut car.as_slice(), CarIndexCodec::MultihashSorted)
+            .await
+            .unwrap();
+        let index_async_std =
+            crate::generate_index(&mut car.as_slice(), CarIndexCodec::MultihashSorted)
+                .await
+                .unwrap();
+        assert_eq!(index, index_async_std);
+
+        let mut car_v2 = Cursor::new(Vec::new());
+        let options = CarV2WriterOptions {
+            index_codec: CarIndexCodec::MultihashSorted,
+            ..Default::default()
+        };
+        wrap_v1(&mut car.as_slice(), &mut car_v2, options)
+            .await
+            .unwrap();
+        let car_v2 = car_v2.into_inner();
+        let expected = std::fs::read("./tests/go_car_fixtures/sample-wrapped-v2.car").unwrap();
+        assert!(car_v2 == expected);
+
+        let mut car_v1 = vec![];
+        extract_v1(&mut car_v2.as_slice(), &mut car_v1)
+            .await
+            .unwrap();
+        assert!(car_v1 == car);
+    }
+}
@@ -2,8 +2,13 @@ use std::io;
 
 use futures::{AsyncRead, AsyncReadExt};
 
+use crate::{
+    car_reader_options::Strictness,
+    error::{CarDecodeError, SpecViolation},
+};
+
 // Max size of u64 varint
-const U64_LEN: usize = 10;
+pub(crate) const U64_LEN: usize = 10;
 
 pub(crate) async fn read_varint_u64<R: AsyncRead + Unpin>(
     stream: &mut R,
@@ -27,37 +32,76 @@ pub(crate) async fn read_varint_u64<R: AsyncRead + Unpin>(
     Ok(None)
 }
 
+/// Decodes a varint from the start of `buf`. Returns `None` if `buf` ends before the
+/// varint does, or if the varint is longer than the max size of a u64 varint.
+pub(crate) fn decode_varint_u64(buf: &[u8]) -> Option<(u64, usize)> {
+    let mut result: u64 = 0;
+
+    for (i, byte) in buf.iter().take(U64_LEN).enumerate() {
+        result |= u64::from(byte & 0b0111_1111) << (i * 7);
+
+        // If is last byte = leftmost bit is zero
+        if byte & 0b1000_0000 == 0 {
+            return Some((result, i + 1));
+        }
+    }
+
+    None
+}
+
+// Implementation copied from https://github.com/paritytech/unsigned-varint/blob/a3a5b8f2bee1f44270629e96541adf805a53d32c/src/encode.rs#L22
+pub(crate) fn encode_varint_u64(input: u64, buf: &mut [u8; U64_LEN]) -> &[u8] {
+    let mut n = input;
+    let mut i = 0;
+    for b in buf.iter_mut() {
+        *b = n as u8 | 0b1000_0000;
+        n >>= 7;
+        if n == 0 {
+            *b &= 0b0111_1111;
+            break;
+        }
+        i += 1
+    }
+    debug_assert_eq!(n, 0);
+    &buf[0..=i]
+}
+
+/// Byte length of the minimal varint encoding of `value`
+pub(crate) fn varint_len_u64(value: u64) -> usize {
+    encode_varint_u64(value, &mut [0u8; U64_LEN]).len()
+}
+
+/// Rejects a varint, or a CID made of varints, of `len` bytes whose minimal encoding is
+/// `minimal_len` bytes. Only with [`Strictness::Strict`].
+pub(crate) fn check_minimal_encoding(
+    len: usize,
+    minimal_len: usize,
+    strictness: Strictness,
+) -> Result<(), CarDecodeError> {
+    if strictness == Strictness::Strict && len != minimal_len {
+        return Err(CarDecodeError::from(SpecViolation::NonMinimalVarint {
+            len,
+            minimal_len,
+        }));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use futures::io::Cursor;
     use quickcheck_macros::quickcheck;
 
     use super::U64_LEN;
-    use crate::varint::read_varint_u64;
-
-    // Implementation copied from https://github.com/paritytech/unsigned-varint/blob/a3a5b8f2bee1f44270629e96541adf805a53d32c/src/encode.rs#L22
-    fn encode_varint_u64(input: u64, buf: &mut [u8; U64_LEN]) -> (&[u8], usize) {
-        let mut n = input;
-        let mut i = 0;
-        for b in buf.iter_mut() {
-            *b = n as u8 | 0b1000_0000;
-            n >>= 7;
-            if n == 0 {
-                *b &= 0b0111_1111;
-                break;
-            }
-            i += 1
-        }
-        debug_assert_eq!(n, 0);
-        (&buf[0..=i], i + 1)
-    }
+    use crate::varint::{decode_varint_u64, encode_varint_u64, read_varint_u64};
 
     // quickcheck macro constructs a test function that runs the assertion below with random inputs,
     // and attempting to find counter examples efficiently
     #[quickcheck]
     fn varint_u64_identity(input: u64) -> bool {
         let mut buf = [0u8; U64_LEN];
-        let (buf_ref, input_len) = encode_varint_u64(input, &mut buf);
+        let buf_ref = encode_varint_u64(input, &mut buf);
+        let input_len = buf_ref.len();
 
         // Cursor = easy way to get AsyncRead from an AsRef<[u8]>
         let mut input_stream = Cursor::new(buf_ref);
@@ -66,4 +110,12 @@ mod tests {
 
         (input, input_len) == output.unwrap().unwrap()
     }
+
+    #[quickcheck]
+    fn varint_u64_identity_sync(input: u64) -> bool {
+        let mut buf = [0u8; U64_LEN];
+        let buf_ref = encode_varint_u64(input, &mut buf);
+
+        Some((input, buf_ref.len())) == decode_varint_u64(buf_ref)
+    }
 }
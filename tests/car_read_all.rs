@@ -47,12 +47,12 @@ macro_rules! car_read_all_test {
 car_read_all_test!(
     go_car_fixture_sample_corrupt_pragma,
     "tests/go_car_fixtures/sample-corrupt-pragma.car",
-    TestResult::Error("IoError(Kind(UnexpectedEof))")
+    TestResult::Error("io error: unexpected end of file at byte 0")
 );
 car_read_all_test!(
     go_car_fixture_sample_rootless_v42,
     "tests/go_car_fixtures/sample-rootless-v42.car",
-    TestResult::Error("UnsupportedCarVersion { version: 42 }")
+    TestResult::Error("unsupported CAR version 42 at byte 0")
 );
 car_read_all_test!(
     go_car_fixture_sample_rw_bs_v2,
@@ -77,22 +77,24 @@ car_read_all_test!(
 car_read_all_test!(
     go_car_fixture_sample_v1_tailing_corrupt_section,
     "tests/go_car_fixtures/sample-v1-tailing-corrupt-section.car",
-    TestResult::Error("IoError(Kind(UnexpectedEof))")
+    TestResult::Error("io error: unexpected end of file in section 1048 at byte 479518")
 );
 car_read_all_test!(
     go_car_fixture_sample_v1_with_zero_len_section,
     "tests/go_car_fixtures/sample-v1-with-zero-len-section.car",
-    TestResult::Error("InvalidBlockHeader(\"zero length\")")
+    TestResult::Error("invalid section header: zero length in section 1049 at byte 479907")
 );
 car_read_all_test!(
     go_car_fixture_sample_v1_with_zero_len_section2,
     "tests/go_car_fixtures/sample-v1-with-zero-len-section2.car",
-    TestResult::Error("InvalidBlockHeader(\"zero length\")")
+    TestResult::Error("invalid section header: zero length in section 20 at byte 20819")
 );
 car_read_all_test!(
     go_car_fixture_sample_v2_corrupt_data_and_index,
     "tests/go_car_fixtures/sample-v2-corrupt-data-and-index.car",
-    TestResult::Error("InvalidCarV1Header(\"padding len too big 18446744073709550203\")")
+    TestResult::Error(
+        "invalid CARv2 header: data payload end overflows (data offset 18446744073709550254, data size 479907, index offset 478545) at byte 11"
+    )
 );
 car_read_all_test!(
     go_car_fixture_sample_v2_indexless,
@@ -0,0 +1,286 @@
+//! Arbitrary bytes fed to every public decoding entry point must return an error, never panic
+
+use futures::{executor::block_on, io::Cursor, AsyncReadExt};
+use ipld_core::cid::multihash::Multihash;
+use quickcheck_macros::quickcheck;
+use rs_car::{
+    extract_v1, extract_v1_with_options, generate_index, generate_index_with_options, wrap_v1,
+    wrap_v1_with_options, CarDecodeError, CarDecoder, CarDecoderEvent, CarIndex, CarIndexCodec,
+    CarIndexedReader, CarReader, CarReaderOptions, CarV2HeaderError, CarV2WriterOptions, Cid,
+    Strictness,
+};
+
+const CODE_SHA2_256: u64 = 0x12;
+const CODEC_RAW: u64 = 0x55;
+
+/// Decodes `car` with all readers, ignoring the results
+fn decode_all(car: &[u8]) -> bool {
+    for validate_block_hash in [true, false] {
+        let _ = block_on(rs_car::car_read_all(&mut &car[..], validate_block_hash));
+        let _ = rs_car::sync::car_read_all(&mut &car[..], validate_block_hash);
+        #[cfg(feature = "tokio")]
+        let _ = block_on(rs_car::tokio::car_read_all(
+            &mut &car[..],
+            validate_block_hash,
+        ));
+    }
+
+    for options in reader_options() {
+        read_block_readers(car, &options);
+        decode_chunks(car, &options);
+        read_indexed(car, &options);
+        for codec in [CarIndexCodec::Sorted, CarIndexCodec::MultihashSorted] {
+            let _ = block_on(generate_index_with_options(
+                &mut &car[..],
+                codec,
+                options.clone(),
+            ));
+        }
+        let _ = block_on(extract_v1_with_options(
+            &mut &car[..],
+            &mut vec![],
+            options.clone(),
+        ));
+        let _ = block_on(wrap_v1_with_options(
+            &mut &car[..],
+            &mut Cursor::new(vec![]),
+            CarV2WriterOptions::default(),
+            options,
+        ));
+    }
+
+    let _ = CarIndex::decode(car);
+    for codec in [CarIndexCodec::Sorted, CarIndexCodec::MultihashSorted] {
+        let _ = block_on(generate_index(&mut &car[..], codec));
+    }
+    let _ = block_on(extract_v1(&mut &car[..], &mut vec![]));
+    let _ = block_on(wrap_v1(
+        &mut &car[..],
+        &mut Cursor::new(vec![]),
+        CarV2WriterOptions::default(),
+    ));
+
+    true
+}
+
+/// Default options with and without hash validation, and strict options with limits small
+/// enough to be hit by the fixtures
+fn reader_options() -> [CarReaderOptions; 3] {
+    [
+        CarReaderOptions::default(),
+        CarReaderOptions::default().with_validate_block_hash(false),
+        CarReaderOptions::default()
+            .with_strictness(Strictness::Strict)
+            .with_max_header_len(64)
+            .with_max_block_len(64)
+            .with_max_padding_len(8)
+            .with_max_roots(1)
+            .with_max_total_bytes(Some(512))
+            .with_max_index_len(64),
+    ]
+}
+
+fn read_block_readers(car: &[u8], options: &CarReaderOptions) {
+    block_on(async {
+        let Ok(mut car_reader) = CarReader::with_options(car, options.clone()).await else {
+            return;
+        };
+        while let Some(Ok(mut block_reader)) = car_reader.next_block_reader().await {
+            let mut block = vec![];
+            if block_reader.read_to_end(&mut block).await.is_err() {
+                break;
+            }
+        }
+        let _ = car_reader.read_index().await;
+    });
+
+    let Ok(mut car_reader) = rs_car::sync::CarReader::with_options(car, options.clone()) else {
+        return;
+    };
+    while let Some(Ok(mut block_reader)) = car_reader.next_block_reader() {
+        if std::io::copy(&mut block_reader, &mut std::io::sink()).is_err() {
+            break;
+        }
+    }
+    let _ = car_reader.read_index();
+}
+
+fn decode_chunks(car: &[u8], options: &CarReaderOptions) {
+    let mut decoder = CarDecoder::with_options(options.clone());
+    for chunk in car.chunks(7) {
+        if decoder.push(chunk).is_err() {
+            return;
+        }
+        loop {
+            match decoder.next_event() {
+                Ok(CarDecoderEvent::Header(_)) | Ok(CarDecoderEvent::Block(_, _)) => {}
+                Ok(CarDecoderEvent::NeedMoreData) | Ok(CarDecoderEvent::End) => break,
+                Err(_) => return,
+            }
+        }
+    }
+    let _ = decoder.finish();
+}
+
+fn read_indexed(car: &[u8], options: &CarReaderOptions) {
+    block_on(async {
+        let Ok(mut car_reader) =
+            CarIndexedReader::with_options(Cursor::new(car.to_vec()), options.clone()).await
+        else {
+            return;
+        };
+
+        let mut cids = car_reader.header.roots.clone();
+        match car_reader.index() {
+            CarIndex::Sorted(index) => cids.extend(
+                index
+                    .iter()
+                    .filter_map(|(digest, _)| Multihash::wrap(CODE_SHA2_256, digest).ok())
+                    .map(|mh| Cid::new_v1(CODEC_RAW, mh)),
+            ),
+            CarIndex::MultihashSorted(index) => cids.extend(
+                index
+                    .iter()
+                    .filter_map(|(code, digest, _)| Multihash::wrap(code, digest).ok())
+                    .map(|mh| Cid::new_v1(CODEC_RAW, mh)),
+            ),
+        }
+
+        for cid in cids {
+            let _ = car_reader.get(&cid).await;
+            let _ = car_reader.get_size(&cid).await;
+        }
+    });
+}
+
+/// Replaces bytes of `car` at the positions of `mutations`, wrapping around its length
+fn mutate(mut car: Vec<u8>, mutations: Vec<(usize, u8)>) -> Vec<u8> {
+    let len = car.len();
+    for (pos, byte) in mutations {
+        car[pos % len] = byte;
+    }
+    car
+}
+
+#[quickcheck]
+fn no_panic_arbitrary_bytes(car: Vec<u8>) -> bool {
+    decode_all(&car)
+}
+
+#[quickcheck]
+fn no_panic_carv1_header_then_arbitrary_bytes(tail: Vec<u8>) -> bool {
+    // CARv1 header of ./tests/spec_fixtures/carv1-basic.car, 1 byte varint + 99 bytes
+    let mut car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+    car.truncate(100);
+    car.extend(tail);
+    decode_all(&car)
+}
+
+#[quickcheck]
+fn no_panic_carv2_pragma_then_arbitrary_bytes(tail: Vec<u8>) -> bool {
+    let mut car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+    car.truncate(11);
+    car.extend(tail);
+    decode_all(&car)
+}
+
+#[quickcheck]
+fn no_panic_mutated_carv1(mutations: Vec<(usize, u8)>) -> bool {
+    let car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+    decode_all(&mutate(car, mutations))
+}
+
+#[quickcheck]
+fn no_panic_mutated_carv2(mutations: Vec<(usize, u8)>) -> bool {
+    // Has an index, to reach the indexed reader
+    let car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+    decode_all(&mutate(car, mutations))
+}
+
+#[quickcheck]
+fn no_panic_truncated_carv2(len: usize) -> bool {
+    let mut car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+    car.truncate(len % car.len());
+    decode_all(&car)
+}
+
+#[test]
+fn no_panic_large_multihash_digest_size() {
+    // CARv1 header then a section whose CIDv1 multihash declares a 200 byte digest
+    let mut car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+    car.truncate(100);
+    car.extend(hex::decode("0a015512c80100000000").unwrap());
+    decode_all(&car);
+
+    let err = block_on(rs_car::car_read_all(&mut &car[..], true)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid multihash: digest size 200 > max 64 in section 0 at byte 100"
+    );
+}
+
+#[test]
+fn no_panic_cid_longer_than_section() {
+    let mut car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+    car.truncate(100);
+    car.extend([1, 1, 0, 0, 0]);
+    decode_all(&car);
+
+    let err = block_on(generate_index(&mut &car[..], CarIndexCodec::Sorted)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid section header: CID runs past the section length 1 in section 0 at byte 100"
+    );
+}
+
+#[test]
+fn no_panic_invalid_cid_version() {
+    let mut car = std::fs::read("./tests/spec_fixtures/carv1-basic.car").unwrap();
+    car.truncate(100);
+    car.extend(hex::decode("0a0755120000000000").unwrap());
+    decode_all(&car);
+}
+
+#[test]
+fn no_panic_carv2_data_offset_before_header_end() {
+    let mut car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+    // data_offset at byte 11 + 16
+    car[27..35].copy_from_slice(&10u64.to_le_bytes());
+    decode_all(&car);
+
+    let err = block_on(rs_car::car_read_all(&mut &car[..], true)).unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            CarDecodeError::InvalidCarV2Header {
+                reason: CarV2HeaderError::DataOffsetBeforeHeaderEnd,
+                data_offset: 10,
+                ..
+            }
+        ),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn no_panic_carv2_data_size_smaller_than_header() {
+    let mut car = std::fs::read("./tests/spec_fixtures/carv2-basic.car").unwrap();
+    // data_size at byte 11 + 24
+    car[35..43].copy_from_slice(&1u64.to_le_bytes());
+    decode_all(&car);
+
+    let err = block_on(rs_car::car_read_all(&mut &car[..], true)).unwrap_err();
+    assert!(
+        matches!(
+            &err,
+            CarDecodeError::InvalidCarV2Header {
+                reason: CarV2HeaderError::DataEndInsideHeader { .. },
+                data_size: 1,
+                ..
+            }
+        ),
+        "{}",
+        err
+    );
+}